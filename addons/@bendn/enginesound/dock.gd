@@ -0,0 +1,316 @@
+@tool
+extends VBoxContainer
+## Plays an [EngineStream] in the editor and edits its [EngineSoundConfig].
+## Select a stream or a config in the inspector to tune it, or start from the default engine with "New".
+
+## [property, label, min, max, step] of the values edited per cylinder
+const CYLINDER_VALUES := [
+	[&"crank_offset", "crank offset", 0.0, 1.0, 0.001],
+	[&"ignition_time", "ignition time", 0.01, 0.5, 0.001],
+	[&"ignition_factor", "ignition", 0.0, 5.0, 0.01],
+	[&"piston_motion_factor", "piston motion", 0.0, 5.0, 0.01],
+]
+## [property, label] of the pipes of a cylinder
+const CYLINDER_PIPES := [
+	[&"intake_waveguide", "intake"],
+	[&"exhaust_waveguide", "exhaust"],
+	[&"extractor_waveguide", "extractor"],
+]
+## the properties a preset replaces
+const PRESET_PROPERTIES := [
+	&"cylinders",
+	&"straight_pipe",
+	&"muffler_elements",
+	&"intake_noise_cutoff",
+	&"engine_vibration_cutoff",
+	&"crankshaft_fluctuation_cutoff",
+]
+
+var undo_redo: EditorUndoRedoManager
+
+## the played stream: the edited one, or [member preview] for a config on its own
+var stream: EngineStream
+## the edited config, null while the stream plays the default engine
+var config: EngineSoundConfig
+## plays configs that were not selected through a stream
+var preview := EngineStream.new()
+## [spin box, object, property] of every edited value
+var bindings := []
+## the parts of the config the controls were made for
+var shown := []
+## whether the dialog saves or loads
+var saving := false
+
+var player := AudioStreamPlayer.new()
+var title := Label.new()
+var play := CheckButton.new()
+var simulate := CheckBox.new()
+var rpm := HSlider.new()
+var throttle := HSlider.new()
+var readout := Label.new()
+var parts := VBoxContainer.new()
+var dialog := EditorFileDialog.new()
+
+func _ready() -> void:
+	add_child(player)
+
+	var files := HBoxContainer.new()
+	files.add_child(button("New", func() -> void: edit(EngineSoundConfig.new())))
+	files.add_child(button("Load preset", open_dialog.bind(false)))
+	files.add_child(button("Save preset", open_dialog.bind(true)))
+	add_child(files)
+	title.text_overrun_behavior = TextServer.OVERRUN_TRIM_ELLIPSIS
+	add_child(title)
+
+	var controls := HBoxContainer.new()
+	play.text = "Play"
+	play.toggled.connect(_on_play_toggled)
+	controls.add_child(play)
+	simulate.text = "Simulate"
+	simulate.tooltip_text = "Drive the engine speed with the throttle, instead of setting it"
+	simulate.toggled.connect(_on_simulate_toggled)
+	controls.add_child(simulate)
+	add_child(controls)
+	add_child(slider_row("rpm", rpm, 500.0, 8000.0, 10.0, 883.0))
+	rpm.value_changed.connect(func(value: float) -> void: stream.engine_rpm = value)
+	add_child(slider_row("throttle", throttle, 0.0, 1.0, 0.01, 0.0))
+	throttle.value_changed.connect(func(value: float) -> void: stream.throttle = value)
+	add_child(readout)
+
+	var scroll := ScrollContainer.new()
+	scroll.size_flags_vertical = SIZE_EXPAND_FILL
+	scroll.horizontal_scroll_mode = ScrollContainer.SCROLL_MODE_DISABLED
+	parts.size_flags_horizontal = SIZE_EXPAND_FILL
+	scroll.add_child(parts)
+	add_child(scroll)
+
+	dialog.access = EditorFileDialog.ACCESS_RESOURCES
+	dialog.add_filter("*.tres, *.res", "Engine presets")
+	dialog.file_selected.connect(_on_file_selected)
+	add_child(dialog)
+
+	edit(EngineSoundConfig.new())
+
+func _process(_delta: float) -> void:
+	if stream != preview and stream.config != config:
+		# the stream's config was replaced, eg. by an undo
+		edit(stream)
+	readout.text = "%d rpm, limiter -%.1f dB" % [stream.get_rpm(), stream.get_gain_reduction()]
+
+## Previews `object`, a [EngineStream] or a [EngineSoundConfig], and edits its config.
+func edit(object: Object) -> void:
+	if config and config.changed.is_connected(_on_config_changed):
+		config.changed.disconnect(_on_config_changed)
+	if object is EngineStream:
+		stream = object
+		config = stream.config
+		title.text = "stream: %s" % name_of(stream)
+	else:
+		stream = preview
+		config = object
+		preview.config = config
+		title.text = "config: %s" % name_of(config)
+	if config:
+		config.changed.connect(_on_config_changed)
+	stream.simulate = simulate.button_pressed
+	stream.engine_rpm = rpm.value
+	stream.throttle = throttle.value
+	if player.stream != stream:
+		player.stream = stream
+		if play.button_pressed:
+			player.play()
+	show_parts()
+
+func name_of(resource: Resource) -> String:
+	return resource.resource_path.get_file() if resource.resource_path else "unsaved"
+
+## the cylinders and pipes the controls are made for
+func parts_of(of: EngineSoundConfig) -> Array:
+	var found := []
+	for cylinder in of.cylinders:
+		found.append(cylinder)
+		for pipe in CYLINDER_PIPES:
+			found.append(cylinder.get(pipe[0]))
+	found.append(of.straight_pipe)
+	found.append_array(of.muffler_elements)
+	return found
+
+## Makes the controls for every part of the config.
+func show_parts() -> void:
+	for child in parts.get_children():
+		parts.remove_child(child)
+		child.queue_free()
+	bindings.clear()
+	shown = []
+	if not config:
+		var label := Label.new()
+		label.text = "The stream plays the default engine."
+		parts.add_child(label)
+		parts.add_child(button("Make a config", make_config))
+		return
+	shown = parts_of(config)
+
+	for i in config.cylinders.size():
+		var cylinder: CylinderConfig = config.cylinders[i]
+		var header := HBoxContainer.new()
+		var label := Label.new()
+		label.text = "Cylinder %d" % (i + 1)
+		label.size_flags_horizontal = SIZE_EXPAND_FILL
+		header.add_child(label)
+		header.add_child(button("Remove", remove_cylinder.bind(i)))
+		parts.add_child(header)
+		var grid := make_grid()
+		for value in CYLINDER_VALUES:
+			add_value(grid, value[1], cylinder, value[0], value[2], value[3], value[4])
+		for pipe in CYLINDER_PIPES:
+			var waveguide: WaveguideConfig = cylinder.get(pipe[0])
+			if waveguide:
+				add_value(grid, "%s length (m)" % pipe[1], waveguide, &"length", 0.001, 1.0, 0.001)
+		parts.add_child(grid)
+	parts.add_child(button("Add cylinder", add_cylinder))
+
+	var muffler := Label.new()
+	muffler.text = "Muffler"
+	parts.add_child(muffler)
+	var pipes := make_grid()
+	if config.straight_pipe:
+		add_value(pipes, "straight pipe (m)", config.straight_pipe, &"length", 0.01, 5.0, 0.01)
+	for i in config.muffler_elements.size():
+		add_value(pipes, "chamber %d (m)" % (i + 1), config.muffler_elements[i], &"length", 0.001, 1.0, 0.001)
+	parts.add_child(pipes)
+
+func make_grid() -> GridContainer:
+	var made := GridContainer.new()
+	made.columns = 2
+	return made
+
+func button(text: String, pressed: Callable) -> Button:
+	var made := Button.new()
+	made.text = text
+	made.pressed.connect(pressed)
+	return made
+
+func slider_row(text: String, slider: HSlider, min_value: float, max_value: float, step: float, value: float) -> HBoxContainer:
+	var row := HBoxContainer.new()
+	var label := Label.new()
+	label.text = text
+	label.custom_minimum_size.x = 64
+	row.add_child(label)
+	slider.min_value = min_value
+	slider.max_value = max_value
+	slider.step = step
+	slider.value = value
+	slider.size_flags_horizontal = SIZE_EXPAND_FILL
+	slider.size_flags_vertical = SIZE_SHRINK_CENTER
+	row.add_child(slider)
+	return row
+
+## Adds a spin box for `property`, read and written through its getter and setter.
+func add_value(to: GridContainer, text: String, object: Object, property: StringName, min_value: float, max_value: float, step: float) -> void:
+	var label := Label.new()
+	label.text = text
+	to.add_child(label)
+	var spin := SpinBox.new()
+	spin.min_value = min_value
+	spin.max_value = max_value
+	spin.step = step
+	spin.allow_greater = true
+	spin.size_flags_horizontal = SIZE_EXPAND_FILL
+	spin.value = object.call("get_%s" % property)
+	spin.value_changed.connect(set_value.bind(object, property))
+	to.add_child(spin)
+	bindings.append([spin, object, property])
+
+func set_value(value: float, object: Object, property: StringName) -> void:
+	undo_redo.create_action("Set %s" % property, UndoRedo.MERGE_ENDS, config)
+	undo_redo.add_do_method(object, "set_%s" % property, value)
+	undo_redo.add_undo_method(object, "set_%s" % property, object.call("get_%s" % property))
+	undo_redo.commit_action()
+
+func set_cylinders(cylinders: Array[CylinderConfig], action: String) -> void:
+	undo_redo.create_action(action, UndoRedo.MERGE_DISABLE, config)
+	undo_redo.add_do_property(config, &"cylinders", cylinders)
+	undo_redo.add_undo_property(config, &"cylinders", config.cylinders)
+	undo_redo.commit_action()
+
+## Adds a copy of the last cylinder.
+func add_cylinder() -> void:
+	var cylinders: Array[CylinderConfig] = []
+	cylinders.assign(config.cylinders)
+	cylinders.append(cylinders.back().copy() if cylinders else CylinderConfig.new())
+	set_cylinders(cylinders, "Add cylinder")
+
+func remove_cylinder(index: int) -> void:
+	var cylinders: Array[CylinderConfig] = []
+	cylinders.assign(config.cylinders)
+	cylinders.remove_at(index)
+	set_cylinders(cylinders, "Remove cylinder")
+
+## Gives the stream a config, starting from the default engine.
+func make_config() -> void:
+	undo_redo.create_action("Make engine config", UndoRedo.MERGE_DISABLE, stream)
+	undo_redo.add_do_property(stream, &"config", EngineSoundConfig.new())
+	undo_redo.add_undo_property(stream, &"config", null)
+	undo_redo.commit_action()
+
+func open_dialog(save: bool) -> void:
+	saving = save
+	dialog.file_mode = EditorFileDialog.FILE_MODE_SAVE_FILE if save else EditorFileDialog.FILE_MODE_OPEN_FILE
+	dialog.title = "Save engine preset" if save else "Load engine preset"
+	dialog.popup_centered_ratio(0.5)
+
+func _on_file_selected(path: String) -> void:
+	if saving:
+		save_preset(path)
+	else:
+		load_preset(path)
+
+func save_preset(path: String) -> void:
+	if not config:
+		push_error("the stream plays the default engine, there is no config to save")
+		return
+	# a copy, so the config keeps its place and its parts are saved inside the preset
+	var error := ResourceSaver.save(config.copy(), path)
+	if error != OK:
+		push_error("can't save the preset to %s: %s" % [path, error_string(error)])
+		return
+	EditorInterface.get_resource_filesystem().update_file(path)
+
+func load_preset(path: String) -> void:
+	var preset := ResourceLoader.load(path) as EngineSoundConfig
+	if not preset:
+		push_error("%s is not an engine preset" % path)
+		return
+	# a copy, so tuning doesn't change the preset
+	preset = preset.copy()
+	if not config:
+		undo_redo.create_action("Load engine preset", UndoRedo.MERGE_DISABLE, stream)
+		undo_redo.add_do_property(stream, &"config", preset)
+		undo_redo.add_undo_property(stream, &"config", null)
+		undo_redo.commit_action()
+		return
+	undo_redo.create_action("Load engine preset", UndoRedo.MERGE_DISABLE, config)
+	for property in PRESET_PROPERTIES:
+		undo_redo.add_do_property(config, property, preset.get(property))
+		undo_redo.add_undo_property(config, property, config.get(property))
+	undo_redo.commit_action()
+
+func _on_config_changed() -> void:
+	if parts_of(config) != shown:
+		show_parts()
+		return
+	for binding in bindings:
+		binding[0].set_value_no_signal(binding[1].call("get_%s" % binding[2]))
+
+func _on_play_toggled(on: bool) -> void:
+	if on:
+		player.play()
+		if simulate.button_pressed:
+			stream.start()
+	else:
+		player.stop()
+
+func _on_simulate_toggled(on: bool) -> void:
+	stream.simulate = on
+	if on and player.playing:
+		stream.start()
@@ -0,0 +1,23 @@
+@tool
+extends EditorPlugin
+
+const Dock := preload("dock.gd")
+
+var dock: Dock
+
+func _enter_tree() -> void:
+	dock = Dock.new()
+	dock.name = "Engine"
+	dock.undo_redo = get_undo_redo()
+	add_control_to_dock(DOCK_SLOT_RIGHT_UL, dock)
+
+func _exit_tree() -> void:
+	remove_control_from_docks(dock)
+	dock.queue_free()
+
+func _handles(object: Object) -> bool:
+	return object is EngineSoundConfig or object is EngineStream
+
+func _edit(object: Object) -> void:
+	if object:
+		dock.edit(object)
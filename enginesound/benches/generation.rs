@@ -1,9 +1,10 @@
-use enginesound::gen::{Engine, Generator, LowPassFilter};
+use enginesound::filter::DcBlocker;
+use enginesound::gen::{Engine, Generator};
 const RATE: u32 = 42000;
 
 fn gen() -> Generator {
-    let engine = Engine::new(RATE);
-    let mut generator = Generator::new(RATE, engine, LowPassFilter::new(0.5, RATE));
+    let engine = Engine::new(RATE).unwrap();
+    let mut generator = Generator::new(RATE, engine, DcBlocker::new(0.5, RATE).into()).unwrap();
     generator
 }
 
@@ -0,0 +1,117 @@
+//! ## Body module ##
+//!
+//! The structure around the engine, ringing along with its vibrations:
+//! ```
+//! cylinders ==> (block) ==mounts==> (body panels) ==> (cabin air)
+//! ```
+//! Every part is a bank of modal resonators. The block and the panels are heard in the engine bay,
+//! the cabin's air modes are the boom heard from the inside.
+//!
+#![warn(clippy::suboptimal_flops, clippy::use_self, clippy::dbg_macro)]
+use crate::error::Error;
+use crate::filter::{Biquad, Shape};
+use crate::gen::LowPassFilter;
+use crate::utils::FExt;
+
+/// One resonance of a part.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mode {
+    pub resonator: Biquad,
+    pub gain: f32,
+}
+
+impl Mode {
+    pub fn new(freq: f32, q: f32, gain: f32, samples_per_second: u32) -> Self {
+        Self {
+            resonator: Biquad::new(Shape::BandPass, freq, q, samples_per_second),
+            gain,
+        }
+    }
+}
+
+fn ring(modes: &mut [Mode], excitation: f32) -> f32 {
+    modes.iter_mut().fold(0.0, |sum, mode| {
+        mode.resonator.filter(excitation).madd(mode.gain, sum)
+    })
+}
+
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Body {
+    /// modes of the engine block, excited by the vibrations directly
+    pub block: Box<[Mode]>,
+    /// modes of the body panels, excited through the engine mounts
+    pub panels: Box<[Mode]>,
+    /// modes of the air in the cabin, excited by the panels
+    pub cabin: Box<[Mode]>,
+    /// how much of the vibration the engine mounts pass on to the body
+    pub mount_transmission: f32,
+    /// the rubber mounts damp the high frequencies
+    pub mount_lp: LowPassFilter,
+    /// volume of the block and panels, relative to the vibrations
+    pub bay_volume: f32,
+    /// volume of the cabin boom
+    pub cabin_volume: f32,
+}
+
+impl Body {
+    /// A car with the engine in front of the cabin.
+    pub fn new(samples_per_second: u32) -> Result<Self, Error> {
+        let sps = samples_per_second;
+        Ok(Self {
+            block: vec![
+                Mode::new(420.0, 8.0, 0.3, sps),
+                Mode::new(880.0, 10.0, 0.2, sps),
+                Mode::new(1350.0, 12.0, 0.1, sps),
+            ]
+            .into(),
+            panels: vec![
+                Mode::new(38.0, 6.0, 0.6, sps),
+                Mode::new(65.0, 8.0, 0.5, sps),
+                Mode::new(110.0, 10.0, 0.3, sps),
+            ]
+            .into(),
+            cabin: vec![
+                Mode::new(42.0, 5.0, 1.0, sps),
+                Mode::new(85.0, 6.0, 0.5, sps),
+            ]
+            .into(),
+            mount_transmission: 0.5,
+            mount_lp: LowPassFilter::new(200.0, sps)?,
+            bay_volume: 1.0,
+            cabin_volume: 2.0,
+        })
+    }
+
+    pub fn resample(&mut self, samples_per_second: u32) {
+        self.block
+            .iter_mut()
+            .chain(self.panels.iter_mut())
+            .chain(self.cabin.iter_mut())
+            .for_each(|mode| mode.resonator.resample(samples_per_second));
+        self.mount_lp.resample(samples_per_second);
+    }
+
+    pub fn clear(&mut self) {
+        self.block
+            .iter_mut()
+            .chain(self.panels.iter_mut())
+            .chain(self.cabin.iter_mut())
+            .for_each(|mode| mode.resonator.state = [0.0; 4]);
+        self.mount_lp.last = 0.0;
+    }
+
+    /// takes in the engine's vibrations
+    /// returns `(engine bay, cabin boom)`, the engine bay is added to the vibrations and the cabin boom is at its own volume
+    pub fn process(&mut self, vibration: f32) -> (f32, f32) {
+        let block = ring(&mut self.block, vibration);
+        let mounts = self.mount_lp.filter(vibration + block) * self.mount_transmission;
+        let panels = ring(&mut self.panels, mounts);
+        let cabin = ring(&mut self.cabin, panels);
+        (
+            (block + panels) * self.bay_volume,
+            cabin * self.cabin_volume,
+        )
+    }
+}
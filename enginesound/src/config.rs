@@ -0,0 +1,570 @@
+//! ## Config module ##
+//!
+//! Engine descriptions as Godot resources, to be saved as `.tres` and shared between `EngineStream`s:
+//! - `EngineSoundConfig`: the cylinders, the muffler and the filters
+//! - `CylinderConfig`: one cylinder with its waveguides
+//! - `WaveguideConfig`: one pipe
+//!
+//! Every resource emits `changed` when it is edited, and passes on the `changed` of its parts,
+//! so the streams using a config rebuild their engine.
+//!
+use crate::error::Error;
+use crate::gen::{Cylinder, Engine, LowPassFilter, WaveGuide};
+use crate::validate::MIN_IGNITION_TIME;
+use godot::engine::{IResource, Resource};
+use godot::obj::Inherits;
+use godot::prelude::*;
+
+/// the rate the defaults are read from `Engine::new` at, any will do as the delays are in seconds
+const DEFAULT_RATE: u32 = 48000;
+/// in m/s, turns the delays into pipe lengths
+const SPEED_OF_SOUND: f32 = 343.0;
+
+fn default_engine() -> Engine {
+    Engine::new(DEFAULT_RATE).expect("the default engine builds at any rate")
+}
+
+/// Makes `part` emit `changed` on `whole` too.
+fn attach<T: Inherits<Resource>>(part: &Gd<T>, whole: &Gd<impl Inherits<Resource>>) {
+    let mut part = part.clone().upcast::<Resource>();
+    let callable = Callable::from_object_method(whole, "emit_changed");
+    if !part.is_connected("changed".into(), callable.clone()) {
+        part.connect("changed".into(), callable);
+    }
+}
+
+/// Undoes `attach`.
+fn detach<T: Inherits<Resource>>(part: &Gd<T>, whole: &Gd<impl Inherits<Resource>>) {
+    let mut part = part.clone().upcast::<Resource>();
+    let callable = Callable::from_object_method(whole, "emit_changed");
+    if part.is_connected("changed".into(), callable.clone()) {
+        part.disconnect("changed".into(), callable);
+    }
+}
+
+/// the pipe of `slot`, or `fallback` if the slot is empty
+fn waveguide(
+    slot: &Option<Gd<WaveguideConfig>>,
+    fallback: &WaveGuide,
+    samples_per_second: u32,
+) -> Result<WaveGuide, Error> {
+    slot.as_ref().map_or_else(
+        || Ok(fallback.clone()),
+        |pipe| pipe.bind().waveguide(samples_per_second),
+    )
+}
+
+/// A pipe, reflecting the pressure waves at both ends.
+#[derive(GodotClass)]
+#[class(base=Resource)]
+pub struct WaveguideConfig {
+    /// The time a wave takes to travel through the pipe, in seconds. Longer pipes sound lower.
+    #[var(get, set = set_delay)]
+    #[export(range = (0.00002, 0.01, 0.000001, or_greater))]
+    delay: f32,
+    /// How much of a wave is reflected at the start of the pipe, from -1 to 1.
+    #[var(get, set = set_alpha)]
+    #[export(range = (-1.0, 1.0, 0.001))]
+    alpha: f32,
+    /// How much of a wave is reflected at the end of the pipe, from -1 to 1.
+    #[var(get, set = set_beta)]
+    #[export(range = (-1.0, 1.0, 0.001))]
+    beta: f32,
+    base: Base<Resource>,
+}
+
+#[godot_api]
+impl IResource for WaveguideConfig {
+    fn init(base: Base<Resource>) -> Self {
+        Self {
+            delay: 0.001,
+            alpha: 0.0,
+            beta: 0.0,
+            base,
+        }
+    }
+}
+
+#[godot_api]
+impl WaveguideConfig {
+    #[func]
+    fn set_delay(&mut self, delay: f32) {
+        self.delay = delay;
+        self.base_mut().emit_changed();
+    }
+
+    #[func]
+    fn set_alpha(&mut self, alpha: f32) {
+        self.alpha = alpha;
+        self.base_mut().emit_changed();
+    }
+
+    #[func]
+    fn set_beta(&mut self, beta: f32) {
+        self.beta = beta;
+        self.base_mut().emit_changed();
+    }
+
+    /// Returns the length of the pipe in meters, the distance a wave travels in [member delay].
+    #[func]
+    fn get_length(&self) -> f32 {
+        self.delay * SPEED_OF_SOUND
+    }
+
+    /// Sets [member delay] to the time a wave takes to travel `length` meters.
+    #[func]
+    fn set_length(&mut self, length: f32) {
+        self.set_delay(length / SPEED_OF_SOUND);
+    }
+
+    /// Returns a copy of this pipe.
+    #[func]
+    fn copy(&self) -> Gd<Self> {
+        Gd::from_init_fn(|base| Self {
+            delay: self.delay,
+            alpha: self.alpha,
+            beta: self.beta,
+            base,
+        })
+    }
+}
+
+impl WaveguideConfig {
+    pub fn from_waveguide(pipe: &WaveGuide) -> Gd<Self> {
+        Gd::from_init_fn(|base| Self {
+            delay: pipe.chamber0.samples.delay,
+            alpha: pipe.alpha,
+            beta: pipe.beta,
+            base,
+        })
+    }
+
+    pub fn waveguide(&self, samples_per_second: u32) -> Result<WaveGuide, Error> {
+        WaveGuide::new(
+            (self.delay * samples_per_second as f32) as usize,
+            self.alpha,
+            self.beta,
+            samples_per_second,
+        )
+    }
+}
+
+/// A cylinder with its intake, exhaust and extractor pipes.
+#[derive(GodotClass)]
+#[class(base=Resource)]
+pub struct CylinderConfig {
+    /// Where this cylinder fires in the cycle, from 0 to 1.
+    #[var(get, set = set_crank_offset)]
+    #[export(range = (0.0, 1.0, 0.001))]
+    crank_offset: f32,
+    /// The pipe from the cylinder to the intake. Empty keeps the default engine's.
+    #[var(get, set = set_intake_waveguide)]
+    #[export]
+    intake_waveguide: Option<Gd<WaveguideConfig>>,
+    /// The pipe from the cylinder to the exhaust. Empty keeps the default engine's.
+    #[var(get, set = set_exhaust_waveguide)]
+    #[export]
+    exhaust_waveguide: Option<Gd<WaveguideConfig>>,
+    /// The pipe from the exhaust pipe to the exhaust collector. Empty keeps the default engine's.
+    #[var(get, set = set_extractor_waveguide)]
+    #[export]
+    extractor_waveguide: Option<Gd<WaveguideConfig>>,
+    /// How much the intake pipe reflects at the cylinder while the intake valve is open.
+    #[var(get, set = set_intake_open_refl)]
+    #[export(range = (-1.0, 1.0, 0.001))]
+    intake_open_refl: f32,
+    /// How much the intake pipe reflects at the cylinder while the intake valve is closed.
+    #[var(get, set = set_intake_closed_refl)]
+    #[export(range = (-1.0, 1.0, 0.001))]
+    intake_closed_refl: f32,
+    /// How much the exhaust pipe reflects at the cylinder while the exhaust valve is open.
+    #[var(get, set = set_exhaust_open_refl)]
+    #[export(range = (-1.0, 1.0, 0.001))]
+    exhaust_open_refl: f32,
+    /// How much the exhaust pipe reflects at the cylinder while the exhaust valve is closed.
+    #[var(get, set = set_exhaust_closed_refl)]
+    #[export(range = (-1.0, 1.0, 0.001))]
+    exhaust_closed_refl: f32,
+    /// The volume of the piston pushing the air around.
+    #[var(get, set = set_piston_motion_factor)]
+    #[export(range = (0.0, 5.0, or_greater))]
+    piston_motion_factor: f32,
+    /// The volume of the combustion.
+    #[var(get, set = set_ignition_factor)]
+    #[export(range = (0.0, 5.0, or_greater))]
+    ignition_factor: f32,
+    /// The time the fuel takes to burn, in fractions of a cycle, at least 0.01.
+    #[var(get, set = set_ignition_time)]
+    #[export(range = (0.01, 0.5, 0.001))]
+    ignition_time: f32,
+    base: Base<Resource>,
+}
+
+#[godot_api]
+impl IResource for CylinderConfig {
+    fn init(base: Base<Resource>) -> Self {
+        Self::with_base(&default_engine().cylinders[0], base)
+    }
+}
+
+#[godot_api]
+impl CylinderConfig {
+    #[func]
+    fn set_crank_offset(&mut self, crank_offset: f32) {
+        self.crank_offset = crank_offset;
+        self.base_mut().emit_changed();
+    }
+
+    #[func]
+    fn set_intake_waveguide(&mut self, pipe: Option<Gd<WaveguideConfig>>) {
+        self.replace_pipe(|config| &mut config.intake_waveguide, pipe);
+    }
+
+    #[func]
+    fn set_exhaust_waveguide(&mut self, pipe: Option<Gd<WaveguideConfig>>) {
+        self.replace_pipe(|config| &mut config.exhaust_waveguide, pipe);
+    }
+
+    #[func]
+    fn set_extractor_waveguide(&mut self, pipe: Option<Gd<WaveguideConfig>>) {
+        self.replace_pipe(|config| &mut config.extractor_waveguide, pipe);
+    }
+
+    #[func]
+    fn set_intake_open_refl(&mut self, refl: f32) {
+        self.intake_open_refl = refl;
+        self.base_mut().emit_changed();
+    }
+
+    #[func]
+    fn set_intake_closed_refl(&mut self, refl: f32) {
+        self.intake_closed_refl = refl;
+        self.base_mut().emit_changed();
+    }
+
+    #[func]
+    fn set_exhaust_open_refl(&mut self, refl: f32) {
+        self.exhaust_open_refl = refl;
+        self.base_mut().emit_changed();
+    }
+
+    #[func]
+    fn set_exhaust_closed_refl(&mut self, refl: f32) {
+        self.exhaust_closed_refl = refl;
+        self.base_mut().emit_changed();
+    }
+
+    #[func]
+    fn set_piston_motion_factor(&mut self, factor: f32) {
+        self.piston_motion_factor = factor;
+        self.base_mut().emit_changed();
+    }
+
+    #[func]
+    fn set_ignition_factor(&mut self, factor: f32) {
+        self.ignition_factor = factor;
+        self.base_mut().emit_changed();
+    }
+
+    #[func]
+    fn set_ignition_time(&mut self, time: f32) {
+        // what `Engine::validate` would clamp it to, so the inspector shows the value that is played
+        self.ignition_time = time.max(MIN_IGNITION_TIME);
+        self.base_mut().emit_changed();
+    }
+
+    /// Returns a copy of this cylinder, with copies of its pipes.
+    #[func]
+    fn copy(&self) -> Gd<Self> {
+        let copy =
+            |pipe: &Option<Gd<WaveguideConfig>>| pipe.as_ref().map(|pipe| pipe.bind().copy());
+        let cylinder = Gd::from_init_fn(|base| Self {
+            crank_offset: self.crank_offset,
+            intake_waveguide: copy(&self.intake_waveguide),
+            exhaust_waveguide: copy(&self.exhaust_waveguide),
+            extractor_waveguide: copy(&self.extractor_waveguide),
+            intake_open_refl: self.intake_open_refl,
+            intake_closed_refl: self.intake_closed_refl,
+            exhaust_open_refl: self.exhaust_open_refl,
+            exhaust_closed_refl: self.exhaust_closed_refl,
+            piston_motion_factor: self.piston_motion_factor,
+            ignition_factor: self.ignition_factor,
+            ignition_time: self.ignition_time,
+            base,
+        });
+        cylinder.bind().watch();
+        cylinder
+    }
+}
+
+impl CylinderConfig {
+    fn with_base(cylinder: &Cylinder, base: Base<Resource>) -> Self {
+        Self {
+            crank_offset: cylinder.crank_offset,
+            intake_waveguide: Some(WaveguideConfig::from_waveguide(&cylinder.intake_waveguide)),
+            exhaust_waveguide: Some(WaveguideConfig::from_waveguide(&cylinder.exhaust_waveguide)),
+            extractor_waveguide: Some(WaveguideConfig::from_waveguide(
+                &cylinder.extractor_waveguide,
+            )),
+            intake_open_refl: cylinder.intake_open_refl,
+            intake_closed_refl: cylinder.intake_closed_refl,
+            exhaust_open_refl: cylinder.exhaust_open_refl,
+            exhaust_closed_refl: cylinder.exhaust_closed_refl,
+            piston_motion_factor: cylinder.piston_motion_factor,
+            ignition_factor: cylinder.ignition_factor,
+            ignition_time: cylinder.ignition_time,
+            base,
+        }
+    }
+
+    pub fn from_cylinder(cylinder: &Cylinder) -> Gd<Self> {
+        Gd::from_init_fn(|base| Self::with_base(cylinder, base))
+    }
+
+    fn pipes(&self) -> impl Iterator<Item = &Gd<WaveguideConfig>> {
+        self.intake_waveguide
+            .iter()
+            .chain(self.exhaust_waveguide.iter())
+            .chain(self.extractor_waveguide.iter())
+    }
+
+    fn replace_pipe(
+        &mut self,
+        slot: fn(&mut Self) -> &mut Option<Gd<WaveguideConfig>>,
+        pipe: Option<Gd<WaveguideConfig>>,
+    ) {
+        let this = self.to_gd();
+        if let Some(old) = slot(self) {
+            detach(old, &this);
+        }
+        if let Some(pipe) = &pipe {
+            attach(pipe, &this);
+        }
+        *slot(self) = pipe;
+        self.base_mut().emit_changed();
+    }
+
+    /// Passes on the `changed` of the pipes.
+    fn watch(&self) {
+        let this = self.to_gd();
+        for pipe in self.pipes() {
+            attach(pipe, &this);
+        }
+    }
+
+    /// the cylinder, with the pipes of `fallback` in the empty slots
+    pub fn cylinder(
+        &self,
+        fallback: &Cylinder,
+        samples_per_second: u32,
+    ) -> Result<Cylinder, Error> {
+        let sps = samples_per_second;
+        Ok(Cylinder {
+            crank_offset: self.crank_offset,
+            intake_waveguide: waveguide(&self.intake_waveguide, &fallback.intake_waveguide, sps)?,
+            exhaust_waveguide: waveguide(
+                &self.exhaust_waveguide,
+                &fallback.exhaust_waveguide,
+                sps,
+            )?,
+            extractor_waveguide: waveguide(
+                &self.extractor_waveguide,
+                &fallback.extractor_waveguide,
+                sps,
+            )?,
+            intake_open_refl: self.intake_open_refl,
+            intake_closed_refl: self.intake_closed_refl,
+            exhaust_open_refl: self.exhaust_open_refl,
+            exhaust_closed_refl: self.exhaust_closed_refl,
+            piston_motion_factor: self.piston_motion_factor,
+            ignition_factor: self.ignition_factor,
+            ignition_time: self.ignition_time,
+            ..Default::default()
+        })
+    }
+}
+
+/// A complete engine: the cylinders, the muffler and the filters.
+/// Save it as a `.tres` and assign it to any number of [EngineStream]s.
+/// The volumes and the valve timing are set on the [EngineStream].
+#[derive(GodotClass)]
+#[class(base=Resource)]
+pub struct EngineSoundConfig {
+    /// The cylinders, all exhausting into one collector.
+    #[var(get, set = set_cylinders)]
+    #[export]
+    cylinders: Array<Gd<CylinderConfig>>,
+    /// The pipe from the exhaust collector to the muffler. Empty keeps the default engine's.
+    #[var(get, set = set_straight_pipe)]
+    #[export]
+    straight_pipe: Option<Gd<WaveguideConfig>>,
+    /// The chambers of the muffler, side by side at the end of the straight pipe.
+    #[var(get, set = set_muffler_elements)]
+    #[export]
+    muffler_elements: Array<Gd<WaveguideConfig>>,
+    /// The cutoff frequency of the air noise in the intake, in Hz.
+    #[var(get, set = set_intake_noise_cutoff)]
+    #[export(range = (20.0, 20000.0, or_greater))]
+    intake_noise_cutoff: f32,
+    /// The cutoff frequency of the engine block's vibrations, in Hz.
+    #[var(get, set = set_engine_vibration_cutoff)]
+    #[export(range = (1.0, 1000.0, or_greater))]
+    engine_vibration_cutoff: f32,
+    /// How fast the crankshaft speed fluctuates, as a cutoff frequency in Hz.
+    #[var(get, set = set_crankshaft_fluctuation_cutoff)]
+    #[export(range = (0.1, 100.0, or_greater))]
+    crankshaft_fluctuation_cutoff: f32,
+    base: Base<Resource>,
+}
+
+#[godot_api]
+impl IResource for EngineSoundConfig {
+    fn init(base: Base<Resource>) -> Self {
+        let engine = default_engine();
+        let mut cylinders = Array::new();
+        for cylinder in engine.cylinders.iter() {
+            cylinders.push(CylinderConfig::from_cylinder(cylinder));
+        }
+        let mut muffler_elements = Array::new();
+        for element in engine.muffler.muffler_elements.iter() {
+            muffler_elements.push(WaveguideConfig::from_waveguide(element));
+        }
+        Self {
+            cylinders,
+            straight_pipe: Some(WaveguideConfig::from_waveguide(
+                &engine.muffler.straight_pipe,
+            )),
+            muffler_elements,
+            intake_noise_cutoff: engine.intake_noise_lp.freq(),
+            engine_vibration_cutoff: engine.engine_vibration_filter.freq(),
+            crankshaft_fluctuation_cutoff: engine.crankshaft_fluctuation_lp.freq(),
+            base,
+        }
+    }
+}
+
+#[godot_api]
+impl EngineSoundConfig {
+    #[func]
+    fn set_cylinders(&mut self, cylinders: Array<Gd<CylinderConfig>>) {
+        let this = self.to_gd();
+        for cylinder in self.cylinders.iter_shared() {
+            detach(&cylinder, &this);
+        }
+        self.cylinders = cylinders;
+        self.watch();
+        self.base_mut().emit_changed();
+    }
+
+    #[func]
+    fn set_straight_pipe(&mut self, pipe: Option<Gd<WaveguideConfig>>) {
+        let this = self.to_gd();
+        if let Some(old) = &self.straight_pipe {
+            detach(old, &this);
+        }
+        self.straight_pipe = pipe;
+        self.watch();
+        self.base_mut().emit_changed();
+    }
+
+    #[func]
+    fn set_muffler_elements(&mut self, elements: Array<Gd<WaveguideConfig>>) {
+        let this = self.to_gd();
+        for element in self.muffler_elements.iter_shared() {
+            detach(&element, &this);
+        }
+        self.muffler_elements = elements;
+        self.watch();
+        self.base_mut().emit_changed();
+    }
+
+    #[func]
+    fn set_intake_noise_cutoff(&mut self, freq: f32) {
+        self.intake_noise_cutoff = freq;
+        self.base_mut().emit_changed();
+    }
+
+    #[func]
+    fn set_engine_vibration_cutoff(&mut self, freq: f32) {
+        self.engine_vibration_cutoff = freq;
+        self.base_mut().emit_changed();
+    }
+
+    #[func]
+    fn set_crankshaft_fluctuation_cutoff(&mut self, freq: f32) {
+        self.crankshaft_fluctuation_cutoff = freq;
+        self.base_mut().emit_changed();
+    }
+
+    /// Returns a copy of this config with copies of all its parts, to edit without changing this one.
+    #[func]
+    fn copy(&self) -> Gd<Self> {
+        let mut cylinders = Array::new();
+        for cylinder in self.cylinders.iter_shared() {
+            cylinders.push(cylinder.bind().copy());
+        }
+        let mut muffler_elements = Array::new();
+        for element in self.muffler_elements.iter_shared() {
+            muffler_elements.push(element.bind().copy());
+        }
+        let copy = Gd::from_init_fn(|base| Self {
+            cylinders,
+            straight_pipe: self.straight_pipe.as_ref().map(|pipe| pipe.bind().copy()),
+            muffler_elements,
+            intake_noise_cutoff: self.intake_noise_cutoff,
+            engine_vibration_cutoff: self.engine_vibration_cutoff,
+            crankshaft_fluctuation_cutoff: self.crankshaft_fluctuation_cutoff,
+            base,
+        });
+        copy.bind().watch();
+        copy
+    }
+}
+
+impl EngineSoundConfig {
+    /// Passes on the `changed` of every part, including the pipes of the cylinders.
+    pub fn watch(&self) {
+        let this = self.to_gd();
+        for cylinder in self.cylinders.iter_shared() {
+            attach(&cylinder, &this);
+            cylinder.bind().watch();
+        }
+        for pipe in self
+            .straight_pipe
+            .iter()
+            .cloned()
+            .chain(self.muffler_elements.iter_shared())
+        {
+            attach(&pipe, &this);
+        }
+    }
+
+    /// The engine of `Engine::new` with this config's cylinders, muffler and filters.
+    /// Empty pipe slots keep the default engine's pipes.
+    /// Parameters out of their safe range are clamped, with a warning.
+    pub fn engine(&self, samples_per_second: u32) -> Result<Engine, Error> {
+        let sps = samples_per_second;
+        let mut engine = Engine::new(sps)?;
+        engine.cylinders = self
+            .cylinders
+            .iter_shared()
+            .map(|cylinder| cylinder.bind().cylinder(&engine.cylinders[0], sps))
+            .collect::<Result<_, _>>()?;
+        engine.muffler.straight_pipe =
+            waveguide(&self.straight_pipe, &engine.muffler.straight_pipe, sps)?;
+        engine.muffler.muffler_elements = self
+            .muffler_elements
+            .iter_shared()
+            .map(|element| element.bind().waveguide(sps))
+            .collect::<Result<_, _>>()?;
+        engine.intake_noise_lp = LowPassFilter::new(self.intake_noise_cutoff, sps)?.into();
+        engine.engine_vibration_filter =
+            LowPassFilter::new(self.engine_vibration_cutoff, sps)?.into();
+        engine.crankshaft_fluctuation_lp =
+            LowPassFilter::new(self.crankshaft_fluctuation_cutoff, sps)?.into();
+        for issue in engine.validate(true) {
+            godot_warn!("clamped {issue}");
+        }
+        Ok(engine)
+    }
+}
@@ -0,0 +1,166 @@
+//! ## Driveline module ##
+//!
+//! Couples the engine to the wheels through a gearbox.
+//! When the clutch is engaged the engine speed follows the wheels, and the straight cut gears whine at the output shaft's speed.
+//!
+#![warn(clippy::suboptimal_flops, clippy::use_self, clippy::dbg_macro)]
+use crate::dynamics::Load;
+use crate::utils::FExt;
+use std::f32::consts::TAU;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Transmission {
+    /// gear ratios, starting at first gear
+    pub ratios: Box<[f32]>,
+    pub reverse_ratio: f32,
+    pub final_drive: f32,
+    /// the selected gear: 0 is neutral, -1 is reverse
+    pub gear: i32,
+    /// rotations per minute of the driven wheels
+    pub wheel_rpm: f32,
+    /// clutch engagement, 0.0 (pedal down) - 1.0 (pedal up)
+    pub clutch: f32,
+    /// teeth on the output shaft's gear
+    pub teeth: f32,
+    pub whine_volume: f32,
+    /// time a shift takes, in seconds
+    pub shift_time: f32,
+    /// how far the engine speed drops while the clutch is open during a shift, 0.0 - 1.0
+    pub shift_dip: f32,
+
+    // running values
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub target_gear: i32,
+    /// seconds left until the shift is over
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub shift_left: f32,
+    /// the engine speed after coupling it to the wheels
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub rpm: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub whine_phase: f32,
+}
+
+impl Default for Transmission {
+    /// A six speed gearbox.
+    fn default() -> Self {
+        Self {
+            ratios: vec![3.36, 2.07, 1.43, 1.0, 0.84, 0.56].into(),
+            reverse_ratio: 3.17,
+            final_drive: 3.42,
+            gear: 0,
+            wheel_rpm: 0.0,
+            clutch: 1.0,
+            teeth: 23.0,
+            whine_volume: 0.03,
+            shift_time: 0.25,
+            shift_dip: 0.15,
+            target_gear: 0,
+            shift_left: 0.0,
+            rpm: 0.0,
+            whine_phase: 0.0,
+        }
+    }
+}
+
+impl Transmission {
+    /// the highest gear there is
+    pub fn top_gear(&self) -> i32 {
+        self.ratios.len() as i32
+    }
+
+    /// ratio between the engine and the wheels in `gear`, `None` in neutral.
+    /// Gears past the top gear are the top gear.
+    pub fn ratio(&self, gear: i32) -> Option<f32> {
+        match gear.min(self.top_gear()) {
+            0 => None,
+            ..=-1 => Some(-self.reverse_ratio * self.final_drive),
+            gear => Some(self.ratios[gear as usize - 1] * self.final_drive),
+        }
+    }
+
+    /// Starts shifting into `gear`. The gear is changed halfway through the shift, when the clutch is fully open.
+    /// Gears past the top gear shift into the top gear.
+    pub fn shift(&mut self, gear: i32) {
+        let gear = gear.min(self.top_gear());
+        if gear == self.target_gear {
+            return;
+        }
+        self.target_gear = gear;
+        self.shift_left = self.shift_time;
+    }
+
+    /// How far a shift has opened the clutch, 1.0 when not shifting.
+    pub fn shift_envelope(&self) -> f32 {
+        if self.shift_left <= 0.0 || self.shift_time <= 0.0 {
+            return 1.0;
+        }
+        // opens during the first half and closes during the second half
+        let progress = 1.0 - self.shift_left / self.shift_time;
+        (2.0f32).madd(-progress, 1.0).abs()
+    }
+
+    /// How far the clutch is closed, including the dip of a shift.
+    pub fn engagement(&self) -> f32 {
+        self.shift_envelope() * self.clutch
+    }
+
+    /// Couples `free_rpm`, the speed the engine would run at on its own, to the wheels.
+    /// Returns `(rpm, driveline)`
+    pub fn step(&mut self, free_rpm: f32, samples_per_second: u32) -> (f32, f32) {
+        let driveline = self.advance(samples_per_second);
+        (self.couple(free_rpm), driveline)
+    }
+
+    /// What the engine is connected to, for driving `Dynamics`.
+    pub fn load(&self) -> Load {
+        Load {
+            rpm: self
+                .ratio(self.gear)
+                .map_or(0.0, |ratio| (self.wheel_rpm * ratio).abs()),
+            engagement: self.ratio(self.gear).map_or(0.0, |_| self.engagement()),
+            lift: self.shift_dip.madd(self.shift_envelope() - 1.0, 1.0),
+        }
+    }
+
+    /// Sets the engine speed from `free_rpm` and the wheels, without any inertia.
+    pub fn couple(&mut self, free_rpm: f32) -> f32 {
+        let load = self.load();
+        // the throttle is lifted while the clutch is open
+        let free_rpm = free_rpm * load.lift;
+        self.rpm = (load.rpm - free_rpm).madd(load.engagement, free_rpm);
+        self.rpm
+    }
+
+    /// Advances the shift by one sample, returns the gear whine.
+    pub fn advance(&mut self, samples_per_second: u32) -> f32 {
+        let sps = samples_per_second as f32;
+        if self.shift_left > 0.0 {
+            self.shift_left -= 1.0 / sps;
+            if self.shift_left <= self.shift_time / 2.0 {
+                self.gear = self.target_gear;
+            }
+        } else {
+            self.gear = self.target_gear;
+        }
+
+        let output = (self.wheel_rpm * self.final_drive).abs() / 60.0;
+        let mesh = output * self.teeth;
+        if mesh >= sps / 2.0 {
+            return 0.0;
+        }
+        self.whine_phase = (self.whine_phase + mesh / sps).fract();
+        let whine = (self.whine_phase * 2.0 * TAU)
+            .sinf()
+            .madd(0.4, (self.whine_phase * TAU).sinf());
+
+        // the gears are loudest under load, and barely heard while freewheeling
+        let load = if self.ratio(self.gear).is_some() {
+            self.engagement().madd(0.7, 0.3)
+        } else {
+            0.3
+        };
+        let speed = (output / 50.0).min(1.0);
+        whine * load * speed * self.whine_volume
+    }
+}
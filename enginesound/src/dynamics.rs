@@ -0,0 +1,202 @@
+//! ## Dynamics module ##
+//!
+//! A simple model of the engine's rotation, so the engine speed can be derived from the throttle and the clutch
+//! instead of being set directly.
+//!
+//! The crankshaft is a flywheel accelerated by the torque curve and slowed down by friction.
+//! An engaged clutch pulls it towards the speed of the wheels.
+//!
+#![warn(clippy::suboptimal_flops, clippy::use_self, clippy::dbg_macro)]
+use crate::utils::FExt;
+use std::f32::consts::TAU;
+
+/// What the clutch connects the engine to.
+#[derive(Clone, Copy)]
+pub struct Load {
+    /// the speed the wheels would turn the engine at
+    pub rpm: f32,
+    /// how far the clutch is closed, 0.0 - 1.0
+    pub engagement: f32,
+    /// multiplies the throttle, the driver lifts while shifting
+    pub lift: f32,
+}
+
+impl Default for Load {
+    /// Neutral.
+    fn default() -> Self {
+        Self {
+            rpm: 0.0,
+            engagement: 0.0,
+            lift: 1.0,
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dynamics {
+    /// rotational inertia of the crankshaft and flywheel, in kg·m²
+    pub inertia: f32,
+    /// full throttle torque in Nm, as `(rpm, torque)` pairs sorted by rpm
+    pub torque_curve: Box<[(f32, f32)]>,
+    /// friction torque in Nm
+    pub friction: f32,
+    /// friction torque in Nm added per rpm
+    pub friction_per_rpm: f32,
+    pub idle_rpm: f32,
+    /// throttle the idle control opens per rpm below `idle_rpm`
+    pub idle_gain: f32,
+    /// the rev limiter cuts fuel above this
+    pub redline: f32,
+    /// how far the engine has to fall below `redline` for the rev limiter to give fuel again
+    pub limiter_drop: f32,
+    /// the engine stalls below this, unless it is being started
+    pub stall_rpm: f32,
+    /// torque of the starter motor, in Nm
+    pub starter_torque: f32,
+    /// how fast an engaged clutch pulls the engine to the wheels' speed, per second
+    pub clutch_stiffness: f32,
+    /// 0.0 - 1.0
+    pub throttle: f32,
+
+    // running values
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub rpm: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub running: bool,
+    /// the starter is turning the engine
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub cranking: bool,
+    /// the rev limiter is cutting fuel
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub limiting: bool,
+}
+
+impl Default for Dynamics {
+    /// A two litre petrol engine, already running at idle.
+    fn default() -> Self {
+        Self {
+            inertia: 0.15,
+            torque_curve: vec![
+                (0.0, 80.0),
+                (1000.0, 150.0),
+                (2000.0, 180.0),
+                (3000.0, 200.0),
+                (4500.0, 210.0),
+                (6000.0, 190.0),
+                (7000.0, 160.0),
+                (8000.0, 100.0),
+            ]
+            .into(),
+            friction: 15.0,
+            friction_per_rpm: 0.004,
+            idle_rpm: 850.0,
+            idle_gain: 0.002,
+            redline: 7000.0,
+            limiter_drop: 200.0,
+            stall_rpm: 300.0,
+            starter_torque: 60.0,
+            clutch_stiffness: 30.0,
+            throttle: 0.0,
+            rpm: 850.0,
+            running: true,
+            cranking: false,
+            limiting: false,
+        }
+    }
+}
+
+impl Dynamics {
+    /// A heavy turbo diesel with lots of low end torque, already running at idle.
+    pub fn diesel() -> Self {
+        Self {
+            inertia: 0.35,
+            torque_curve: vec![
+                (0.0, 150.0),
+                (1000.0, 300.0),
+                (1500.0, 400.0),
+                (2000.0, 420.0),
+                (3000.0, 360.0),
+                (4000.0, 250.0),
+                (4500.0, 150.0),
+            ]
+            .into(),
+            friction: 30.0,
+            friction_per_rpm: 0.008,
+            idle_rpm: 750.0,
+            idle_gain: 0.003,
+            redline: 4200.0,
+            limiter_drop: 150.0,
+            stall_rpm: 350.0,
+            starter_torque: 150.0,
+            rpm: 750.0,
+            ..Default::default()
+        }
+    }
+
+    /// full throttle torque at `rpm`, interpolated from the torque curve
+    pub fn torque(&self, rpm: f32) -> f32 {
+        let curve = &self.torque_curve;
+        let Some(&(first_rpm, first)) = curve.first() else {
+            return 0.0;
+        };
+        if rpm <= first_rpm {
+            return first;
+        }
+        for window in curve.windows(2) {
+            let [(rpm0, torque0), (rpm1, torque1)] = [window[0], window[1]];
+            if rpm <= rpm1 {
+                return (torque1 - torque0).madd((rpm - rpm0) / (rpm1 - rpm0), torque0);
+            }
+        }
+        curve[curve.len() - 1].1
+    }
+
+    /// Turns the starter until the engine catches.
+    pub fn start(&mut self) {
+        if !self.running {
+            self.cranking = true;
+        }
+    }
+
+    /// Cuts the ignition.
+    pub fn stop(&mut self) {
+        self.running = false;
+        self.cranking = false;
+    }
+
+    /// Advances the engine by `dt` seconds and returns the new rpm.
+    pub fn step(&mut self, dt: f32, load: Load) -> f32 {
+        if self.rpm >= self.redline {
+            self.limiting = true;
+        } else if self.rpm < self.redline - self.limiter_drop {
+            self.limiting = false;
+        }
+
+        let mut torque = 0.0;
+        if self.running && !self.limiting {
+            let idle = ((self.idle_rpm - self.rpm) * self.idle_gain).clamp(0.0, 1.0);
+            let throttle = (self.throttle * load.lift).max(idle).clamp(0.0, 1.0);
+            torque = self.torque(self.rpm) * throttle;
+        }
+        if self.cranking {
+            torque += self.starter_torque;
+            if self.rpm > self.stall_rpm {
+                self.cranking = false;
+                self.running = true;
+            }
+        }
+        torque -= self.friction_per_rpm.madd(self.rpm, self.friction);
+
+        // rad/s² to rpm/s
+        let acceleration = torque / self.inertia * 60.0 / TAU;
+        self.rpm = acceleration.madd(dt, self.rpm);
+
+        let pull = 1.0 - (-self.clutch_stiffness * load.engagement * dt).exp();
+        self.rpm = (load.rpm - self.rpm).madd(pull, self.rpm).max(0.0);
+
+        if self.running && !self.cranking && self.rpm < self.stall_rpm {
+            self.running = false;
+        }
+        self.rpm
+    }
+}
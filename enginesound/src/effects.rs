@@ -0,0 +1,467 @@
+//! ## Effects module ##
+//!
+//! Processing after the mix, run in order by the `Generator`:
+//! - `Effect::Eq`: parametric equalizer bands
+//! - `Effect::Saturation`: soft clipping
+//! - `Effect::Compressor`: evens out the loudness, or limits it with a high ratio
+//! - `Effect::Reverb`: the reflections of a tunnel or a garage
+//!
+//! The `Limiter` comes after all of them. In stereo, every effect runs on both channels after the panning.
+//!
+#![warn(clippy::suboptimal_flops, clippy::use_self, clippy::dbg_macro)]
+use crate::error::Error;
+use crate::filter::{Biquad, Shape};
+use crate::gen::LoopBuffer;
+use crate::utils::FExt;
+use std::collections::VecDeque;
+
+fn db_to_gain(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.max(1e-9).log10()
+}
+
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Compressor {
+    /// level above which the gain is reduced, in dB
+    pub threshold: f32,
+    /// how many dB above the threshold turn into one dB at the output
+    pub ratio: f32,
+    /// in s
+    pub attack: f32,
+    /// in s
+    pub release: f32,
+    /// gain added after compressing, in dB
+    pub makeup: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub samples_per_second: u32,
+
+    // running values
+    /// the level the gain reduction follows, in dB
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub envelope: f32,
+}
+
+impl Compressor {
+    pub fn new(threshold: f32, ratio: f32, samples_per_second: u32) -> Self {
+        Self {
+            threshold,
+            ratio,
+            attack: 0.005,
+            release: 0.1,
+            makeup: 0.0,
+            samples_per_second,
+            envelope: -120.0,
+        }
+    }
+
+    /// the current gain reduction, in dB
+    pub fn reduction(&self) -> f32 {
+        (self.envelope - self.threshold).max(0.0) * (1.0 - 1.0 / self.ratio.max(1.0))
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        sample * self.follow(sample.abs())
+    }
+
+    /// Follows the `peak` level, returns the gain.
+    fn follow(&mut self, peak: f32) -> f32 {
+        let level = gain_to_db(peak);
+        let time = if level > self.envelope {
+            self.attack
+        } else {
+            self.release
+        };
+        let follow = 1.0 - (-1.0 / (time * self.samples_per_second as f32)).exp();
+        self.envelope = (level - self.envelope).madd(follow, self.envelope);
+        db_to_gain(self.makeup - self.reduction())
+    }
+}
+
+/// A comb filter with a damped feedback path.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Comb {
+    pub buffer: LoopBuffer,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub last: f32,
+}
+
+/// the most feedback the combs get, at 1.0 the tail would grow forever
+pub const MAX_ROOM_SIZE: f32 = 0.98;
+
+/// Schroeder-Moorer reverb: parallel combs followed by allpasses in series.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Reverb {
+    /// feedback of the combs, 0.0 - `MAX_ROOM_SIZE`, longer tails for bigger rooms
+    pub room_size: f32,
+    /// how much the walls absorb the highs, 0.0 - 1.0
+    pub damping: f32,
+    /// the reverberated signal added to the dry one, 0.0 - 1.0
+    pub mix: f32,
+    pub combs: Box<[Comb]>,
+    pub allpasses: Box<[LoopBuffer]>,
+}
+
+impl Reverb {
+    /// A concrete garage.
+    pub fn new(samples_per_second: u32) -> Self {
+        let buffer = |delay: f32| {
+            LoopBuffer::new(
+                (delay * samples_per_second as f32) as usize,
+                samples_per_second,
+            )
+        };
+        Self {
+            room_size: 0.8,
+            damping: 0.3,
+            mix: 0.2,
+            combs: [0.0253, 0.0269, 0.029, 0.0307, 0.0322, 0.0338]
+                .iter()
+                .map(|&delay| Comb {
+                    buffer: buffer(delay),
+                    last: 0.0,
+                })
+                .collect(),
+            allpasses: [0.0126, 0.01, 0.0077]
+                .iter()
+                .map(|&delay| buffer(delay))
+                .collect(),
+        }
+    }
+
+    pub fn resample(&mut self, samples_per_second: u32) -> Result<(), Error> {
+        for comb in self.combs.iter_mut() {
+            comb.buffer.resample(samples_per_second)?;
+        }
+        for allpass in self.allpasses.iter_mut() {
+            allpass.resample(samples_per_second)?;
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        for comb in self.combs.iter_mut() {
+            comb.buffer.data.iter_mut().for_each(|x| *x = 0.0);
+            comb.last = 0.0;
+        }
+        for allpass in self.allpasses.iter_mut() {
+            allpass.data.iter_mut().for_each(|x| *x = 0.0);
+        }
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        self.wet(sample).madd(self.mix.clamp_nan(0.0, 1.0), sample)
+    }
+
+    /// the reverberated `sample`, before the `mix`
+    fn wet(&mut self, sample: f32) -> f32 {
+        // out of range or NaN values would make the tail grow until it is NaN for good
+        let room_size = self.room_size.clamp_nan(0.0, MAX_ROOM_SIZE);
+        let damping = self.damping.clamp_nan(0.0, 1.0);
+        // keeps the tail about as loud as the dry signal, whatever the room size
+        let input = sample * (1.0 - room_size) / self.combs.len().max(1) as f32;
+        let mut wet = 0.0;
+        for comb in self.combs.iter_mut() {
+            let out = comb.buffer.pop();
+            comb.last = (comb.last - out).madd(damping, out);
+            comb.buffer.push(comb.last.madd(room_size, input));
+            comb.buffer.advance();
+            wet += out;
+        }
+        for allpass in self.allpasses.iter_mut() {
+            let delayed = allpass.pop();
+            allpass.push(delayed.madd(0.5, wet));
+            allpass.advance();
+            wet = delayed - wet;
+        }
+        wet
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Effect {
+    /// bands in series
+    Eq(Box<[Biquad]>),
+    /// soft clipping, `drive` (1.0 - ) pushes the signal into the curve, 1.0 is clean
+    Saturation {
+        drive: f32,
+        mix: f32,
+    },
+    Compressor(Compressor),
+    Reverb(Reverb),
+}
+
+impl Effect {
+    /// Three bands: a low shelf at 200 Hz, a bell at 1 kHz and a high shelf at 5 kHz, gains in dB.
+    pub fn eq(low: f32, mid: f32, high: f32, samples_per_second: u32) -> Self {
+        let sps = samples_per_second;
+        Self::Eq(
+            vec![
+                Biquad::new(Shape::LowShelf { gain: low }, 200.0, 0.7, sps),
+                Biquad::new(Shape::Peaking { gain: mid }, 1000.0, 0.7, sps),
+                Biquad::new(Shape::HighShelf { gain: high }, 5000.0, 0.7, sps),
+            ]
+            .into(),
+        )
+    }
+
+    pub fn resample(&mut self, samples_per_second: u32) -> Result<(), Error> {
+        match self {
+            Self::Eq(bands) => bands
+                .iter_mut()
+                .for_each(|band| band.resample(samples_per_second)),
+            Self::Saturation { .. } => {}
+            Self::Compressor(compressor) => compressor.samples_per_second = samples_per_second,
+            Self::Reverb(reverb) => return reverb.resample(samples_per_second),
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        match self {
+            Self::Eq(bands) => bands.iter_mut().for_each(Biquad::clear),
+            Self::Saturation { .. } => {}
+            Self::Compressor(compressor) => compressor.envelope = -120.0,
+            Self::Reverb(reverb) => reverb.clear(),
+        }
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        match self {
+            Self::Eq(bands) => bands
+                .iter_mut()
+                .fold(sample, |sample, band| band.filter(sample)),
+            Self::Saturation { drive, mix } => {
+                let drive = drive.max(1.0);
+                // unity gain for quiet signals, peaks are rounded off towards 1.0 / drive
+                let clipped = (sample * drive).tanh() / drive;
+                // faded in over the first unit of drive, so that 1.0 is clean
+                let mix = *mix * (drive - 1.0).min(1.0);
+                (clipped - sample).madd(mix, sample)
+            }
+            Self::Compressor(compressor) => compressor.process(sample),
+            Self::Reverb(reverb) => reverb.process(sample),
+        }
+    }
+
+    /// `process` for the left and right channel.
+    /// The compressor turns both down by the louder one, and the reverb is of their sum, so they keep their balance.
+    pub fn process_stereo(&mut self, left: f32, right: f32) -> (f32, f32) {
+        match self {
+            Self::Eq(bands) => bands.iter_mut().fold((left, right), |(left, right), band| {
+                band.filter_stereo(left, right)
+            }),
+            Self::Saturation { .. } => (self.process(left), self.process(right)),
+            Self::Compressor(compressor) => {
+                let gain = compressor.follow(left.abs().max(right.abs()));
+                (left * gain, right * gain)
+            }
+            Self::Reverb(reverb) => {
+                let wet = reverb.wet((left + right) * 0.5);
+                let mix = reverb.mix.clamp_nan(0.0, 1.0);
+                (wet.madd(mix, left), wet.madd(mix, right))
+            }
+        }
+    }
+}
+
+/// Look-ahead peak limiter. Delays the output by `lookahead`, to turn the gain down before a peak arrives.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Limiter {
+    /// the output never goes above this, 0.0 - 1.0
+    pub ceiling: f32,
+    /// time the gain takes to recover, in s
+    pub release: f32,
+    /// in s
+    pub lookahead: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub samples_per_second: u32,
+
+    // running values
+    /// the delayed left and right channel
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub delay: [LoopBuffer; 2],
+    /// `(sample index, gain)` of the quietest gains needed within the look-ahead, increasing
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub needed: VecDeque<(usize, f32)>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub index: usize,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub gain: f32,
+}
+
+impl Limiter {
+    pub fn new(ceiling: f32, samples_per_second: u32) -> Self {
+        let mut limiter = Self {
+            ceiling,
+            release: 0.1,
+            lookahead: 0.005,
+            ..Default::default()
+        };
+        limiter.resample(samples_per_second);
+        limiter
+    }
+
+    fn window(&self) -> usize {
+        ((self.lookahead * self.samples_per_second as f32) as usize).max(1)
+    }
+
+    /// Rebuilds the delay at `samples_per_second`. Clears the limiter.
+    pub fn resample(&mut self, samples_per_second: u32) {
+        self.samples_per_second = samples_per_second;
+        let window = self.window();
+        self.delay = [
+            LoopBuffer::new(window + 1, samples_per_second),
+            LoopBuffer::new(window + 1, samples_per_second),
+        ];
+        self.clear();
+    }
+
+    pub fn clear(&mut self) {
+        for delay in self.delay.iter_mut() {
+            delay.data.iter_mut().for_each(|x| *x = 0.0);
+        }
+        self.needed.clear();
+        self.gain = 1.0;
+    }
+
+    /// the current gain reduction, in dB
+    pub fn reduction(&self) -> f32 {
+        -gain_to_db(self.gain)
+    }
+
+    /// takes in and returns `(left, right)`
+    pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let window = self.window();
+        // a negative or NaN ceiling would make the clamp below panic
+        let ceiling = self.ceiling.abs().min(1.0);
+        let peak = left.abs().max(right.abs());
+        let needed = if peak > ceiling { ceiling / peak } else { 1.0 };
+        while self.needed.back().is_some_and(|&(_, gain)| gain >= needed) {
+            self.needed.pop_back();
+        }
+        self.needed.push_back((self.index, needed));
+        while self
+            .needed
+            .front()
+            .is_some_and(|&(index, _)| index + window < self.index)
+        {
+            self.needed.pop_front();
+        }
+        self.index += 1;
+        let target = self.needed.front().map_or(1.0, |&(_, gain)| gain);
+
+        let time = if target < self.gain {
+            // reaches the target within the look-ahead
+            window as f32 / 5.0
+        } else {
+            self.release.max(0.0) * self.samples_per_second as f32
+        };
+        self.gain = (target - self.gain).madd(1.0 - (-1.0 / time).exp(), self.gain);
+
+        let mut out = [left, right];
+        for (delay, out) in self.delay.iter_mut().zip(out.iter_mut()) {
+            let delayed = delay.pop();
+            delay.push(*out);
+            delay.advance();
+            // whatever the look-ahead missed is clipped
+            *out = (delayed * self.gain).clamp(-ceiling, ceiling);
+        }
+        (out[0], out[1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a loud sine with sharp peaks on top, which the look-ahead has to catch
+    fn loud(i: usize) -> f32 {
+        let sine = (i as f32 * 0.05).sin() * 3.0;
+        if i.is_multiple_of(1000) {
+            sine + 8.0
+        } else {
+            sine
+        }
+    }
+
+    #[test]
+    fn limiter_stays_below_the_ceiling() {
+        let mut limiter = Limiter::new(0.8, 48000);
+        for i in 0..48000 {
+            let (left, right) = limiter.process(loud(i), -0.5 * loud(i + 7));
+            assert!(
+                left.abs() <= 0.8 && right.abs() <= 0.8,
+                "{} {}",
+                left,
+                right
+            );
+        }
+        assert!(limiter.reduction() > 0.0);
+    }
+
+    #[test]
+    fn limiter_passes_quiet_signals() {
+        let mut limiter = Limiter::new(0.9, 48000);
+        let delay = limiter.window();
+        let quiet: Vec<f32> = (0..4800).map(|i| (i as f32 * 0.01).sin() * 0.5).collect();
+        let out: Vec<f32> = quiet
+            .iter()
+            .map(|&sample| limiter.process(sample, sample).0)
+            .collect();
+        for (out, sample) in out[delay..].iter().zip(quiet.iter()) {
+            assert!((out - sample).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn reverb_survives_bad_rooms() {
+        for room_size in [1.0, 1.5, f32::NAN] {
+            let mut reverb = Reverb {
+                room_size,
+                mix: f32::NAN,
+                ..Reverb::new(48000)
+            };
+            for i in 0..48000 * 5 {
+                assert!(reverb.process(loud(i)).is_finite());
+            }
+        }
+    }
+
+    #[test]
+    fn stereo_effects_match_mono_in_the_center() {
+        for effect in [
+            Effect::eq(6.0, -3.0, 4.0, 48000),
+            Effect::Saturation {
+                drive: 3.0,
+                mix: 1.0,
+            },
+            Effect::Compressor(Compressor::new(-12.0, 4.0, 48000)),
+            Effect::Reverb(Reverb::new(48000)),
+        ] {
+            let (mut mono, mut stereo) = (effect.clone(), effect);
+            for i in 0..4800 {
+                let sample = mono.process(loud(i));
+                assert_eq!(stereo.process_stereo(loud(i), loud(i)), (sample, sample));
+            }
+        }
+    }
+
+    #[test]
+    fn limiter_survives_bad_ceilings() {
+        for ceiling in [-0.5, f32::NAN, 2.0] {
+            let mut limiter = Limiter::new(ceiling, 48000);
+            for i in 0..4800 {
+                let (left, right) = limiter.process(loud(i), loud(i));
+                assert!(left.abs() <= 1.0 && right.abs() <= 1.0);
+            }
+        }
+    }
+}
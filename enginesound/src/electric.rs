@@ -0,0 +1,249 @@
+//! ## Electric module ##
+//!
+//! Electric drivetrains have no combustion to listen to.
+//! What is left is the whine of the inverter switching the motor windings and the whine of the reduction gears.
+//!
+//! The inverter's PWM carrier is not heard directly, but its sidebands at `f_sw ± 2 * f_e` are,
+//! where `f_e` is the electrical frequency of the motor. These sweep apart as the motor speeds up,
+//! and jump whenever the inverter steps to another switching frequency.
+//!
+#![warn(clippy::suboptimal_flops, clippy::use_self, clippy::dbg_macro)]
+#[cfg(feature = "godot")]
+use godot::{engine::AudioStreamGeneratorPlayback, prelude::*};
+
+use crate::error::{check_rate, Error};
+use crate::filter::Filter;
+use crate::gen::{Generator, LowPassFilter, Noise};
+use crate::utils::FExt;
+
+use std::f32::consts::TAU;
+
+/// One stage of the reduction gearbox.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GearMesh {
+    /// teeth on the gear driving this mesh
+    pub teeth: f32,
+    /// speed of the driving gear relative to the motor
+    pub ratio: f32,
+    pub volume: f32,
+
+    // running values
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub phase: f32,
+}
+
+/// The inverter switches at `frequency` once the motor turns faster than `rpm`.
+#[derive(Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SwitchingStep {
+    pub rpm: f32,
+    pub frequency: f32,
+}
+
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Motor {
+    pub rpm: f32,
+    /// how hard the motor is being driven (0.0 - 1.0), the whine follows the phase current
+    pub load: f32,
+    /// the electrical frequency is `rpm / 60 * pole_pairs`
+    pub pole_pairs: f32,
+
+    pub inverter_volume: f32,
+    /// switching frequencies, sorted by `rpm`
+    pub switching: Box<[SwitchingStep]>,
+    /// the sixth electrical order, from torque ripple
+    pub ripple_volume: f32,
+    pub gears: Box<[GearMesh]>,
+    pub noise_volume: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub noise: Noise,
+    pub noise_lp: LowPassFilter,
+
+    // running values
+    /// phases of the lower and upper PWM sideband
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub sideband_phase: (f32, f32),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub ripple_phase: f32,
+}
+
+impl Motor {
+    /// A permanent magnet motor with a single reduction stage, like in most small EVs.
+    pub fn new(samples_per_second: u32) -> Result<Self, Error> {
+        Ok(Self {
+            rpm: 0.0,
+            load: 0.5,
+            pole_pairs: 4.0,
+            inverter_volume: 0.12,
+            switching: vec![
+                SwitchingStep {
+                    rpm: 0.0,
+                    frequency: 2500.0,
+                },
+                SwitchingStep {
+                    rpm: 2000.0,
+                    frequency: 5000.0,
+                },
+                SwitchingStep {
+                    rpm: 6000.0,
+                    frequency: 8000.0,
+                },
+            ]
+            .into(),
+            ripple_volume: 0.3,
+            gears: vec![
+                GearMesh {
+                    teeth: 19.0,
+                    ratio: 1.0,
+                    volume: 0.4,
+                    ..Default::default()
+                },
+                GearMesh {
+                    teeth: 23.0,
+                    ratio: 1.0 / 3.2,
+                    volume: 0.25,
+                    ..Default::default()
+                },
+            ]
+            .into(),
+            noise_volume: 0.05,
+            noise_lp: LowPassFilter::new(1500.0, samples_per_second)?,
+            ..Default::default()
+        })
+    }
+
+    /// the frequency the inverter switches at, for the current rpm
+    pub fn switching_frequency(&self) -> f32 {
+        self.switching
+            .iter()
+            .take_while(|step| step.rpm <= self.rpm.abs())
+            .last()
+            .map_or(0.0, |step| step.frequency)
+    }
+}
+
+/// Advances `phase` by `freq` and returns the sine at the new phase.
+#[inline]
+fn osc(phase: &mut f32, freq: f32, samples_per_second: f32) -> f32 {
+    *phase = (*phase + freq / samples_per_second).fract();
+    (*phase * TAU).sinf()
+}
+
+/// Generator for electric drivetrains, with the same interface as `Generator`.
+pub struct ElectricGenerator {
+    pub volume: f32,
+    pub samples_per_second: u32,
+    pub motor: Motor,
+    /// highpass applied to the sample while playing back to reduce dc offset and thus clipping, eg. a `DcBlocker`
+    dc_filter: Filter,
+}
+
+impl ElectricGenerator {
+    pub fn new(samples_per_second: u32, motor: Motor, dc_filter: Filter) -> Result<Self, Error> {
+        check_rate(samples_per_second)?;
+        Ok(Self {
+            volume: 0.1_f32,
+            samples_per_second,
+            motor,
+            dc_filter,
+        })
+    }
+
+    #[cfg(feature = "godot")]
+    pub fn generate(&mut self, player: &mut Gd<AudioStreamGeneratorPlayback>) {
+        for _ in 0..player.get_frames_available() {
+            player.push_frame(Vector2::splat(self.frame()));
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.motor.sideband_phase = (0.0, 0.0);
+        self.motor.ripple_phase = 0.0;
+        self.motor.noise_lp.last = 0.0;
+        for gear in self.motor.gears.iter_mut() {
+            gear.phase = 0.0;
+        }
+    }
+
+    pub fn frame(&mut self) -> f32 {
+        let sps = self.samples_per_second as f32;
+        // above nyquist the oscillators would alias, below it they are inaudible anyways
+        let nyquist = sps / 2.0;
+        let motor = &mut self.motor;
+
+        let rev = motor.rpm.abs() / 60.0;
+        let electrical = rev * motor.pole_pairs;
+        let switching = motor.switching_frequency();
+
+        let current = motor.load.abs().sqrt();
+        let mut inverter = 0.0;
+        if switching > 0.0 {
+            let lower = electrical.madd(-2.0, switching);
+            let upper = electrical.madd(2.0, switching);
+            if lower > 0.0 {
+                inverter += osc(&mut motor.sideband_phase.0, lower, sps);
+            }
+            if upper < nyquist {
+                inverter += osc(&mut motor.sideband_phase.1, upper, sps);
+            }
+        }
+
+        let ripple = osc(&mut motor.ripple_phase, 6.0 * electrical, sps);
+
+        let mut gears = 0.0;
+        for gear in motor.gears.iter_mut() {
+            let mesh = rev * gear.ratio * gear.teeth;
+            if mesh < nyquist {
+                gears = osc(&mut gear.phase, mesh, sps).madd(gear.volume, gears);
+            }
+        }
+        // gear whine grows with speed, the motor's with current
+        let speed = (rev / 100.0).min(1.0);
+
+        let noise = motor.noise_lp.filter(motor.noise.step()) * speed;
+
+        let mixed = inverter.madd(
+            motor.inverter_volume * current,
+            ripple.madd(
+                motor.ripple_volume * current,
+                gears.madd(speed, noise * motor.noise_volume),
+            ),
+        ) * self.volume;
+
+        // reduces dc offset
+        self.dc_filter.filter(mixed)
+    }
+}
+
+/// A hybrid drivetrain, mixing a combustion engine with an electric motor.
+pub struct Hybrid {
+    pub combustion: Generator,
+    pub electric: ElectricGenerator,
+}
+
+impl Hybrid {
+    pub fn new(combustion: Generator, electric: ElectricGenerator) -> Self {
+        Self {
+            combustion,
+            electric,
+        }
+    }
+
+    #[cfg(feature = "godot")]
+    pub fn generate(&mut self, player: &mut Gd<AudioStreamGeneratorPlayback>) {
+        for _ in 0..player.get_frames_available() {
+            player.push_frame(Vector2::splat(self.frame()));
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.combustion.reset();
+        self.electric.reset();
+    }
+
+    pub fn frame(&mut self) -> f32 {
+        self.combustion.frame() + self.electric.frame()
+    }
+}
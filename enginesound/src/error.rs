@@ -0,0 +1,64 @@
+//! ## Error module ##
+//!
+//! What can go wrong building an engine.
+//!
+use crate::network::UnknownPipe;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// nothing can be generated at 0 samples per second
+    ZeroSampleRate,
+    /// a delay line without a single sample, too short for the sample rate
+    EmptyDelay,
+    /// a parameter that is NaN or infinite, by name
+    NotFinite(&'static str),
+    /// a size that is 0 or negative, by name
+    NotPositive(&'static str),
+    /// an engine without cylinders or rotors
+    NoCylinders,
+    UnknownPipe(UnknownPipe),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ZeroSampleRate => write!(f, "the sample rate is 0"),
+            Self::EmptyDelay => write!(f, "a delay line is shorter than one sample"),
+            Self::NotFinite(name) => write!(f, "`{name}` is not a finite number"),
+            Self::NotPositive(name) => write!(f, "`{name}` is not above 0"),
+            Self::NoCylinders => write!(f, "the engine has no cylinders"),
+            Self::UnknownPipe(UnknownPipe(name)) => write!(f, "there is no pipe named `{name}`"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<UnknownPipe> for Error {
+    fn from(pipe: UnknownPipe) -> Self {
+        Self::UnknownPipe(pipe)
+    }
+}
+
+pub fn check_rate(samples_per_second: u32) -> Result<(), Error> {
+    if samples_per_second == 0 {
+        return Err(Error::ZeroSampleRate);
+    }
+    Ok(())
+}
+
+pub fn check_finite(name: &'static str, value: f32) -> Result<(), Error> {
+    if !value.is_finite() {
+        return Err(Error::NotFinite(name));
+    }
+    Ok(())
+}
+
+pub fn check_positive(name: &'static str, value: f32) -> Result<(), Error> {
+    check_finite(name, value)?;
+    if value <= 0.0 {
+        return Err(Error::NotPositive(name));
+    }
+    Ok(())
+}
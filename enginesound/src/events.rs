@@ -0,0 +1,184 @@
+//! ## Events module ##
+//!
+//! What happens in the engine, for gameplay effects to follow:
+//! - `Event::Ignition`: a cylinder fired
+//! - `Event::RevLimiter`: the rev limiter started cutting fuel
+//! - `Event::Backfire`: unburnt fuel went off in the exhaust
+//! - `Event::Started` and `Event::Stalled`
+//!
+//! The events are queued while the audio is generated, and timed by the samples generated so far.
+//! Without `Dynamics` the engine always runs, so only the ignitions are recorded.
+//! The rotors of a rotary engine are counted after the cylinders, and fire once per face.
+//!
+#![warn(clippy::suboptimal_flops, clippy::use_self, clippy::dbg_macro)]
+use crate::dynamics::Dynamics;
+use crate::gen::{Engine, Rotor};
+use crate::utils::FExt;
+use std::collections::VecDeque;
+
+/// events kept before the oldest ones are dropped, in case nobody takes them
+pub const MAX_QUEUED: usize = 1024;
+/// the throttle snapping below this at high rpm makes the engine pop
+pub const LIFT_THROTTLE: f32 = 0.05;
+/// the rpm above which a closing throttle makes the engine pop
+pub const LIFT_RPM: f32 = 3500.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// the cylinder of this index fired, the rotors are counted after the cylinders
+    Ignition(usize),
+    /// the rev limiter started cutting fuel
+    RevLimiter,
+    /// the fuel left over from a fuel cut or a closing throttle went off in this cylinder's exhaust
+    Backfire(usize),
+    /// the starter got the engine running
+    Started,
+    /// the engine stopped running, stalled or switched off
+    Stalled,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timed {
+    pub event: Event,
+    /// in s of generated audio
+    pub time: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Events {
+    pub queue: VecDeque<Timed>,
+    /// the clock of the events, reset with the generator
+    pub samples: u64,
+
+    // running values
+    pub running: bool,
+    pub limiting: bool,
+    pub throttle: f32,
+    /// the next firing cylinder backfires
+    pub backfire: bool,
+    /// the position of every rotor without the crankshaft fluctuation, which would make it fire many times
+    pub rotors: Vec<f32>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            samples: 0,
+            running: true,
+            limiting: false,
+            throttle: 0.0,
+            backfire: false,
+            rotors: Vec::new(),
+        }
+    }
+}
+
+impl Events {
+    /// Takes the queued events, oldest first.
+    pub fn take(&mut self) -> Vec<Timed> {
+        self.queue.drain(..).collect()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn push(&mut self, event: Event, time: f64) {
+        if self.queue.len() >= MAX_QUEUED {
+            self.queue.pop_front();
+        }
+        self.queue.push_back(Timed { event, time });
+    }
+
+    /// Records what happened in the last sample, with the crankshaft moved on from `last_crank_pos`
+    /// at `rpm`, the speed it turns at while coupled to the wheels.
+    pub fn record(
+        &mut self,
+        engine: &Engine,
+        dynamics: Option<&Dynamics>,
+        last_crank_pos: f32,
+        rpm: f32,
+        samples_per_second: u32,
+    ) {
+        let time = self.samples as f64 / f64::from(samples_per_second);
+        self.samples += 1;
+
+        let (running, limiting) = dynamics.map_or((true, false), |dynamics| {
+            (dynamics.running, dynamics.limiting)
+        });
+        let throttle = dynamics
+            .map(|dynamics| dynamics.throttle)
+            .or_else(|| engine.intake.as_ref().map(|intake| intake.throttle))
+            .unwrap_or(0.0);
+        if running != self.running {
+            self.push(
+                if running {
+                    Event::Started
+                } else {
+                    Event::Stalled
+                },
+                time,
+            );
+            self.running = running;
+        }
+        if limiting != self.limiting {
+            if limiting {
+                self.push(Event::RevLimiter, time);
+            } else {
+                // the fuel comes back into an exhaust full of unburnt mixture
+                self.backfire = true;
+            }
+            self.limiting = limiting;
+        }
+        if throttle < LIFT_THROTTLE && self.throttle >= LIFT_THROTTLE && rpm > LIFT_RPM {
+            self.backfire = true;
+        }
+        self.throttle = throttle;
+
+        if !running || limiting {
+            return;
+        }
+        let cylinders = engine.cylinders.iter().map(|cylinder| {
+            fires(
+                last_crank_pos + cylinder.crank_offset,
+                engine.crankshaft_pos + cylinder.crank_offset,
+            )
+        });
+        if self.rotors.len() != engine.rotors.len() {
+            self.rotors = engine.rotors.iter().map(|rotor| rotor.rotor_pos).collect();
+        }
+        // a crank cycle is two shaft revolutions, a rotor revolution is three, see `Rotor::pop`
+        let mut delta = engine.crankshaft_pos - last_crank_pos;
+        delta -= delta.round();
+        let rotors = engine
+            .rotors
+            .iter()
+            .zip(self.rotors.iter_mut())
+            .map(|(rotor, pos)| {
+                let before = *pos;
+                *pos = delta.madd(2.0 / 3.0, before).rem_euclid(1.0);
+                (0..Rotor::FACES).any(|face| {
+                    let face = rotor.rotor_offset + face as f32 / Rotor::FACES as f32;
+                    fires(before + face, *pos + face)
+                })
+            });
+        let fired: Vec<usize> = cylinders
+            .chain(rotors)
+            .enumerate()
+            .filter_map(|(i, fired)| fired.then_some(i))
+            .collect();
+        for i in fired {
+            self.push(Event::Ignition(i), time);
+            if self.backfire {
+                self.push(Event::Backfire(i), time);
+                self.backfire = false;
+            }
+        }
+    }
+}
+
+/// whether a position in the cycle went past the firing point at 0.5, see `Ignition::pulse`
+fn fires(before: f32, after: f32) -> bool {
+    before.rem_euclid(1.0) < 0.5 && after.rem_euclid(1.0) >= 0.5
+}
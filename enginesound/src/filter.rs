@@ -0,0 +1,517 @@
+//! ## Filter module ##
+//!
+//! Filters besides the one pole `LowPassFilter`:
+//! - `Biquad`: the RBJ cookbook filters, low-/high-/bandpass, notch, peaking and shelves
+//! - `Svf`: a state variable filter, which stays stable while its frequency is changed every sample
+//! - `DcBlocker`: a one pole highpass, for removing the dc offset
+//! - `Butterworth`: cascaded biquads with a maximally flat passband
+//!
+//! `Filter` holds any of them, so every filter of the `Engine` can be swapped for another.
+//! Only the parameters are serialized; call `resample` after deserializing.
+//!
+#![warn(clippy::suboptimal_flops, clippy::use_self, clippy::dbg_macro)]
+use crate::gen::{LowPassFilter, PI2F};
+use crate::utils::FExt;
+use std::f32::consts::PI;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Shape {
+    #[default]
+    LowPass,
+    HighPass,
+    /// with unity gain at the center frequency
+    BandPass,
+    Notch,
+    /// boosts or cuts around the frequency, by `gain` dB
+    Peaking {
+        gain: f32,
+    },
+    /// boosts or cuts below the frequency, by `gain` dB
+    LowShelf {
+        gain: f32,
+    },
+    /// boosts or cuts above the frequency, by `gain` dB
+    HighShelf {
+        gain: f32,
+    },
+}
+
+impl Shape {
+    /// The same shape boosting or cutting by `gain` dB, if it has a gain.
+    pub fn with_gain(self, gain: f32) -> Self {
+        match self {
+            Self::Peaking { .. } => Self::Peaking { gain },
+            Self::LowShelf { .. } => Self::LowShelf { gain },
+            Self::HighShelf { .. } => Self::HighShelf { gain },
+            shape => shape,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Biquad {
+    pub shape: Shape,
+    pub freq: f32,
+    /// 0.7071 is the flattest, higher values resonate
+    pub q: f32,
+    /// b0, b1, b2, a1, a2, normalized by a0
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub coefficients: [f32; 5],
+    /// the last two inputs and outputs
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub state: [f32; 4],
+    /// the `state` of the right channel, for `filter_stereo`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub right: [f32; 4],
+}
+
+impl Biquad {
+    pub fn new(shape: Shape, freq: f32, q: f32, samples_per_second: u32) -> Self {
+        let omega = PI2F * freq / samples_per_second as f32;
+        let (sin, cos) = omega.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let shelf = |gain: f32| {
+            let a = 10.0_f32.powf(gain / 40.0);
+            (a, 2.0 * a.sqrt() * alpha)
+        };
+        let [b0, b1, b2, a0, a1, a2] = match shape {
+            Shape::LowPass => {
+                let b = (1.0 - cos) / 2.0;
+                [b, 2.0 * b, b, 1.0 + alpha, -2.0 * cos, 1.0 - alpha]
+            }
+            Shape::HighPass => {
+                let b = (1.0 + cos) / 2.0;
+                [b, -2.0 * b, b, 1.0 + alpha, -2.0 * cos, 1.0 - alpha]
+            }
+            Shape::BandPass => [alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            Shape::Notch => [1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            Shape::Peaking { gain } => {
+                let a = 10.0_f32.powf(gain / 40.0);
+                [
+                    alpha.madd(a, 1.0),
+                    -2.0 * cos,
+                    (-alpha).madd(a, 1.0),
+                    1.0 + alpha / a,
+                    -2.0 * cos,
+                    1.0 - alpha / a,
+                ]
+            }
+            Shape::LowShelf { gain } => {
+                let (a, s) = shelf(gain);
+                let (p, m) = (a + 1.0, a - 1.0);
+                [
+                    a * ((-m).madd(cos, p) + s),
+                    2.0 * a * (-p).madd(cos, m),
+                    a * ((-m).madd(cos, p) - s),
+                    m.madd(cos, p) + s,
+                    -2.0 * p.madd(cos, m),
+                    m.madd(cos, p) - s,
+                ]
+            }
+            Shape::HighShelf { gain } => {
+                let (a, s) = shelf(gain);
+                let (p, m) = (a + 1.0, a - 1.0);
+                [
+                    a * (m.madd(cos, p) + s),
+                    -2.0 * a * p.madd(cos, m),
+                    a * (m.madd(cos, p) - s),
+                    (-m).madd(cos, p) + s,
+                    2.0 * (-p).madd(cos, m),
+                    (-m).madd(cos, p) - s,
+                ]
+            }
+        };
+        Self {
+            shape,
+            freq,
+            q,
+            coefficients: [b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0],
+            state: [0.0; 4],
+            right: [0.0; 4],
+        }
+    }
+
+    /// A bandpass at the resonance of a volume of air behind a neck, like an air box or a bottle.
+    /// `volume` is in m³, `neck_area` in m² and `neck_length` in m.
+    pub fn helmholtz(
+        volume: f32,
+        neck_area: f32,
+        neck_length: f32,
+        q: f32,
+        samples_per_second: u32,
+    ) -> Self {
+        const SPEED_OF_SOUND: f32 = 343.0;
+        let freq = SPEED_OF_SOUND / PI2F * (neck_area / (volume * neck_length)).sqrt();
+        Self::new(Shape::BandPass, freq, q, samples_per_second)
+    }
+
+    /// A first order low- or highpass, as a biquad without its second order terms.
+    /// It isn't one of the `Shape`s, so it can't be resampled on its own.
+    fn first_order(shape: Shape, freq: f32, samples_per_second: u32) -> Self {
+        let k = (PI * freq / samples_per_second as f32).tan();
+        let a1 = (k - 1.0) / (k + 1.0);
+        let (b0, b1) = match shape {
+            Shape::HighPass => (1.0 / (k + 1.0), -1.0 / (k + 1.0)),
+            _ => (k / (k + 1.0), k / (k + 1.0)),
+        };
+        Self {
+            shape,
+            freq,
+            q: 0.0,
+            coefficients: [b0, b1, 0.0, a1, 0.0],
+            state: [0.0; 4],
+            right: [0.0; 4],
+        }
+    }
+
+    pub fn resample(&mut self, samples_per_second: u32) {
+        *self = Self::new(self.shape, self.freq, self.q, samples_per_second);
+    }
+
+    /// Changes the filter while keeping its state, so it can be changed while playing.
+    pub fn retune(&mut self, shape: Shape, freq: f32, q: f32, samples_per_second: u32) {
+        *self = Self {
+            state: self.state,
+            right: self.right,
+            ..Self::new(shape, freq, q, samples_per_second)
+        };
+    }
+
+    pub fn clear(&mut self) {
+        self.state = [0.0; 4];
+        self.right = [0.0; 4];
+    }
+
+    #[inline]
+    pub fn filter(&mut self, sample: f32) -> f32 {
+        let [b0, b1, b2, a1, a2] = self.coefficients;
+        let [x1, x2, y1, y2] = self.state;
+        let ret = sample.madd(b0, x1.madd(b1, x2.madd(b2, (-a1).madd(y1, -a2 * y2))));
+        self.state = [sample, x1, ret, y1];
+        ret
+    }
+
+    /// `filter` for the left and right channel, the right one runs on `right`
+    #[inline]
+    pub fn filter_stereo(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let left = self.filter(left);
+        std::mem::swap(&mut self.state, &mut self.right);
+        let right = self.filter(right);
+        std::mem::swap(&mut self.state, &mut self.right);
+        (left, right)
+    }
+}
+
+/// Which output of the `Svf` is used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Mode {
+    #[default]
+    LowPass,
+    HighPass,
+    /// with unity gain at the center frequency
+    BandPass,
+    Notch,
+}
+
+/// State variable filter, with trapezoidal integrators.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Svf {
+    pub mode: Mode,
+    pub freq: f32,
+    pub q: f32,
+    /// g, k, a1, a2, a3
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub coefficients: [f32; 5],
+    /// the integrators
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub state: [f32; 2],
+    /// the `state` of the right channel, for `filter_stereo`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub right: [f32; 2],
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub samples_per_second: u32,
+}
+
+impl Svf {
+    pub fn new(mode: Mode, freq: f32, q: f32, samples_per_second: u32) -> Self {
+        let mut svf = Self {
+            mode,
+            q,
+            samples_per_second,
+            ..Default::default()
+        };
+        svf.set_freq(freq);
+        svf
+    }
+
+    /// Moves the frequency without clearing the filter.
+    pub fn set_freq(&mut self, freq: f32) {
+        self.freq = freq;
+        let g = (PI * freq / self.samples_per_second as f32).tan();
+        let k = 1.0 / self.q;
+        let a1 = 1.0 / g.madd(g + k, 1.0);
+        let a2 = g * a1;
+        self.coefficients = [g, k, a1, a2, g * a2];
+    }
+
+    pub fn resample(&mut self, samples_per_second: u32) {
+        self.samples_per_second = samples_per_second;
+        self.set_freq(self.freq);
+    }
+
+    pub fn clear(&mut self) {
+        self.state = [0.0; 2];
+        self.right = [0.0; 2];
+    }
+
+    /// returns `(lowpass, bandpass, highpass)`, the bandpass peaks at `q`
+    #[inline]
+    pub fn process(&mut self, sample: f32) -> (f32, f32, f32) {
+        let [_, k, a1, a2, a3] = self.coefficients;
+        let [ic1, ic2] = self.state;
+        let v3 = sample - ic2;
+        let v1 = a1.madd(ic1, a2 * v3);
+        let v2 = a2.madd(ic1, a3.madd(v3, ic2));
+        self.state = [2.0f32.madd(v1, -ic1), 2.0f32.madd(v2, -ic2)];
+        (v2, v1, (-k).madd(v1, sample - v2))
+    }
+
+    #[inline]
+    pub fn filter(&mut self, sample: f32) -> f32 {
+        let (low, band, high) = self.process(sample);
+        match self.mode {
+            Mode::LowPass => low,
+            Mode::HighPass => high,
+            Mode::BandPass => band * self.coefficients[1],
+            Mode::Notch => low + high,
+        }
+    }
+
+    /// `filter` for the left and right channel, the right one runs on `right`
+    #[inline]
+    pub fn filter_stereo(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let left = self.filter(left);
+        std::mem::swap(&mut self.state, &mut self.right);
+        let right = self.filter(right);
+        std::mem::swap(&mut self.state, &mut self.right);
+        (left, right)
+    }
+}
+
+/// Removes the dc offset, letting everything above `freq` through.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DcBlocker {
+    pub freq: f32,
+    /// the pole
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub r: f32,
+    /// the last input and output
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub state: [f32; 2],
+    /// the `state` of the right channel, for `filter_stereo`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub right: [f32; 2],
+}
+
+impl DcBlocker {
+    pub fn new(freq: f32, samples_per_second: u32) -> Self {
+        Self {
+            freq,
+            r: (-PI2F * freq / samples_per_second as f32).exp(),
+            state: [0.0; 2],
+            right: [0.0; 2],
+        }
+    }
+
+    pub fn resample(&mut self, samples_per_second: u32) {
+        *self = Self::new(self.freq, samples_per_second);
+    }
+
+    pub fn clear(&mut self) {
+        self.state = [0.0; 2];
+        self.right = [0.0; 2];
+    }
+
+    #[inline]
+    pub fn filter(&mut self, sample: f32) -> f32 {
+        let [x1, y1] = self.state;
+        let ret = self.r.madd(y1, sample - x1);
+        self.state = [sample, ret];
+        ret
+    }
+
+    /// `filter` for the left and right channel, the right one runs on `right`
+    #[inline]
+    pub fn filter_stereo(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let left = self.filter(left);
+        std::mem::swap(&mut self.state, &mut self.right);
+        let right = self.filter(right);
+        std::mem::swap(&mut self.state, &mut self.right);
+        (left, right)
+    }
+}
+
+/// A Butterworth low- or highpass, built from biquads.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Butterworth {
+    /// `Shape::LowPass` or `Shape::HighPass`
+    pub shape: Shape,
+    pub freq: f32,
+    /// at least 1, every two orders are one biquad and an odd order adds a first order section
+    pub order: usize,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub sections: Box<[Biquad]>,
+}
+
+impl Butterworth {
+    pub fn new(shape: Shape, freq: f32, order: usize, samples_per_second: u32) -> Self {
+        let order = order.max(1);
+        let odd = order % 2;
+        Self {
+            shape,
+            freq,
+            order,
+            sections: (0..order / 2)
+                .map(|i| {
+                    // the poles are spread evenly on the half circle, an odd order has one on the real axis
+                    let angle = PI * (2 * i + 1 + odd) as f32 / (2 * order) as f32;
+                    Biquad::new(shape, freq, 1.0 / (2.0 * angle.cos()), samples_per_second)
+                })
+                .chain((odd == 1).then(|| Biquad::first_order(shape, freq, samples_per_second)))
+                .collect(),
+        }
+    }
+
+    pub fn resample(&mut self, samples_per_second: u32) {
+        *self = Self::new(self.shape, self.freq, self.order, samples_per_second);
+    }
+
+    pub fn clear(&mut self) {
+        self.sections.iter_mut().for_each(Biquad::clear);
+    }
+
+    #[inline]
+    pub fn filter(&mut self, sample: f32) -> f32 {
+        self.sections
+            .iter_mut()
+            .fold(sample, |sample, section| section.filter(sample))
+    }
+
+    /// `filter` for the left and right channel
+    #[inline]
+    pub fn filter_stereo(&mut self, left: f32, right: f32) -> (f32, f32) {
+        self.sections
+            .iter_mut()
+            .fold((left, right), |(left, right), section| {
+                section.filter_stereo(left, right)
+            })
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Filter {
+    OnePole(LowPassFilter),
+    Biquad(Biquad),
+    Svf(Svf),
+    DcBlocker(DcBlocker),
+    Butterworth(Butterworth),
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::OnePole(LowPassFilter::default())
+    }
+}
+
+impl Filter {
+    pub fn resample(&mut self, samples_per_second: u32) {
+        match self {
+            Self::OnePole(filter) => filter.resample(samples_per_second),
+            Self::Biquad(filter) => filter.resample(samples_per_second),
+            Self::Svf(filter) => filter.resample(samples_per_second),
+            Self::DcBlocker(filter) => filter.resample(samples_per_second),
+            Self::Butterworth(filter) => filter.resample(samples_per_second),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        match self {
+            Self::OnePole(filter) => filter.clear(),
+            Self::Biquad(filter) => filter.clear(),
+            Self::Svf(filter) => filter.clear(),
+            Self::DcBlocker(filter) => filter.clear(),
+            Self::Butterworth(filter) => filter.clear(),
+        }
+    }
+
+    /// the cutoff or center frequency
+    pub fn freq(&self) -> f32 {
+        match self {
+            Self::OnePole(filter) => 1.0 / filter.delay,
+            Self::Biquad(filter) => filter.freq,
+            Self::Svf(filter) => filter.freq,
+            Self::DcBlocker(filter) => filter.freq,
+            Self::Butterworth(filter) => filter.freq,
+        }
+    }
+
+    #[inline]
+    pub fn filter(&mut self, sample: f32) -> f32 {
+        match self {
+            Self::OnePole(filter) => filter.filter(sample),
+            Self::Biquad(filter) => filter.filter(sample),
+            Self::Svf(filter) => filter.filter(sample),
+            Self::DcBlocker(filter) => filter.filter(sample),
+            Self::Butterworth(filter) => filter.filter(sample),
+        }
+    }
+
+    /// `filter` for the left and right channel
+    #[inline]
+    pub fn filter_stereo(&mut self, left: f32, right: f32) -> (f32, f32) {
+        match self {
+            Self::OnePole(filter) => filter.filter_stereo(left, right),
+            Self::Biquad(filter) => filter.filter_stereo(left, right),
+            Self::Svf(filter) => filter.filter_stereo(left, right),
+            Self::DcBlocker(filter) => filter.filter_stereo(left, right),
+            Self::Butterworth(filter) => filter.filter_stereo(left, right),
+        }
+    }
+}
+
+impl From<LowPassFilter> for Filter {
+    fn from(filter: LowPassFilter) -> Self {
+        Self::OnePole(filter)
+    }
+}
+
+impl From<Biquad> for Filter {
+    fn from(filter: Biquad) -> Self {
+        Self::Biquad(filter)
+    }
+}
+
+impl From<Svf> for Filter {
+    fn from(filter: Svf) -> Self {
+        Self::Svf(filter)
+    }
+}
+
+impl From<DcBlocker> for Filter {
+    fn from(filter: DcBlocker) -> Self {
+        Self::DcBlocker(filter)
+    }
+}
+
+impl From<Butterworth> for Filter {
+    fn from(filter: Butterworth) -> Self {
+        Self::Butterworth(filter)
+    }
+}
@@ -9,23 +9,191 @@
 use godot::{engine::AudioStreamGeneratorPlayback, prelude::*};
 use rand_core::{RngCore, SeedableRng};
 use rand_xorshift::XorShiftRng;
+use std::f32::consts::SQRT_2;
 use std::time::SystemTime;
 
+use crate::body::Body;
+use crate::driveline::Transmission;
+use crate::dynamics::{Dynamics, Load};
+use crate::effects::{Effect, Limiter};
+use crate::error::{check_finite, check_rate, Error};
+use crate::events::Events;
+use crate::filter::Filter;
+use crate::intake::Intake;
+use crate::listener::Listener;
+use crate::mechanical::Mechanical;
+use crate::network::Network;
+use crate::rumble::Rumble;
+use crate::tailpipe::Tailpipe;
 use crate::utils::FExt;
+#[cfg(feature = "serde")]
+use crate::validate::Issue;
 
 pub const PI2F: f32 = 2.0 * std::f32::consts::PI;
 pub const PI4F: f32 = 4.0 * std::f32::consts::PI;
 pub const WAVEGUIDE_MAX_AMP: f32 = 20.0; // at this amplitude, a damping function is applied to fight feedback loops
+/// time a cylinder's `level` takes to fall back after a peak, in s
+pub const LEVEL_RELEASE: f32 = 0.05;
 
 // https://www.researchgate.net/profile/Stefano_Delle_Monache/publication/280086598_Physically_informed_car_engine_sound_synthesis_for_virtual_and_augmented_environments/links/55a791bc08aea2222c746724/Physically-informed-car-engine-sound-synthesis-for-virtual-and-augmented-environments.pdf?origin=publication_detail
 
-#[derive(Default)]
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Muffler {
     pub straight_pipe: WaveGuide,
     pub muffler_elements: Box<[WaveGuide]>,
 }
 
+impl Muffler {
+    /// takes in the exhaust collector pressure and the wave the tailpipe reflects back
+    /// returns `(back pressure, output)`
+    pub fn process(&mut self, exhaust_collector: f32, tailpipe: f32) -> (f32, f32) {
+        // parallel input to the exhaust straight pipe
+        // alpha end is at exhaust collector
+        let straight_pipe_wg_ret = self.straight_pipe.pop();
+
+        // alpha end is at straight pipe end (beta)
+        let mut muffler_wg_ret = (0.0, 0.0);
+
+        for muffler_line in self.muffler_elements.iter_mut() {
+            let ret = muffler_line.pop();
+            muffler_wg_ret.0 += ret.0;
+            muffler_wg_ret.1 += ret.1;
+        }
+
+        self.straight_pipe.push(exhaust_collector, muffler_wg_ret.0);
+
+        let muffler_elements = self.muffler_elements.len() as f32;
+
+        for muffler_delay_line in self.muffler_elements.iter_mut() {
+            muffler_delay_line.push(
+                straight_pipe_wg_ret.1 / muffler_elements,
+                tailpipe / muffler_elements,
+            );
+        }
+
+        (straight_pipe_wg_ret.0, muffler_wg_ret.1)
+    }
+
+    pub fn resample(&mut self, samples_per_second: u32) -> Result<(), Error> {
+        self.straight_pipe.resample(samples_per_second)?;
+        for element in self.muffler_elements.iter_mut() {
+            element.resample(samples_per_second)?;
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        std::iter::once(&mut self.straight_pipe)
+            .chain(self.muffler_elements.iter_mut())
+            .flat_map(|x| [&mut x.chamber0, &mut x.chamber1])
+            .for_each(|chamber| chamber.samples.data.iter_mut().for_each(|x| *x = 0.0));
+    }
+}
+
+/// One bank of cylinders, with its own exhaust collector and muffler.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bank {
+    pub muffler: Muffler,
+    /// replaces the `muffler`, if set
+    pub exhaust: Option<Network>,
+    pub tailpipe: Option<Tailpipe>,
+    /// where this bank is placed in the stereo image, -1.0 (left) - 1.0 (right)
+    pub pan: f32,
+
+    // running values
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub exhaust_collector: f32,
+    /// the collector pressure of the last sample, divided among the cylinders
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub last_exhaust_collector: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub cylinders: f32,
+    /// this bank's exhaust stem
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub output: f32,
+}
+
+impl Bank {
+    pub fn new(muffler: Muffler, pan: f32) -> Self {
+        Self {
+            muffler,
+            pan,
+            ..Default::default()
+        }
+    }
+
+    /// runs the collector through the muffler, setting `output`
+    fn process(&mut self) {
+        let returning = self
+            .tailpipe
+            .as_ref()
+            .map_or(0.0, |tailpipe| tailpipe.returning);
+        let (back_pressure, output) = match &mut self.exhaust {
+            Some(network) => network.process(self.exhaust_collector, returning),
+            None => self.muffler.process(self.exhaust_collector, returning),
+        };
+        self.exhaust_collector += back_pressure;
+        self.output = match &mut self.tailpipe {
+            Some(tailpipe) => tailpipe.process(output),
+            None => output,
+        };
+    }
+}
+
+/// Couples the collectors of the first two banks.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Crossover {
+    /// the collectors merge, exchanging `mix` (0.0 - 1.0) of their pressure
+    X { mix: f32 },
+    /// a balance pipe between the collectors
+    H { pipe: WaveGuide },
+}
+
+impl Crossover {
+    fn couple(&mut self, a: &mut f32, b: &mut f32) {
+        match self {
+            Self::X { mix } => {
+                let avg = (*a + *b) / 2.0;
+                *a = (avg - *a).madd(*mix, *a);
+                *b = (avg - *b).madd(*mix, *b);
+            }
+            Self::H { pipe } => {
+                let ret = pipe.pop();
+                pipe.push(*a, *b);
+                *a += ret.0;
+                *b += ret.1;
+            }
+        }
+    }
+}
+
+/// Adds a cylinder's exhaust to its bank's collector, or the engine's if there are no banks.
+#[inline]
+fn collect(banks: &mut [Bank], engine_collector: &mut f32, bank: usize, exhaust: f32) {
+    match banks.len() {
+        0 => *engine_collector += exhaust,
+        n => {
+            let bank = &mut banks[bank.min(n - 1)];
+            bank.exhaust_collector += exhaust;
+            bank.cylinders += 1.0;
+        }
+    }
+}
+
+/// The collector pressure for a cylinder of `bank`.
+#[inline]
+fn collector(banks: &[Bank], engine_collector: f32, bank: usize) -> f32 {
+    match banks.len() {
+        0 => engine_collector,
+        n => banks[bank.min(n - 1)].last_exhaust_collector,
+    }
+}
+
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Engine {
     pub rpm: f32,
     pub intake_volume: f32,
@@ -33,32 +201,49 @@ pub struct Engine {
     pub engine_vibrations_volume: f32,
 
     pub cylinders: Box<[Cylinder]>,
-    // #[serde(skip)]
+    /// how the cylinders' mixture is ignited
+    pub ignition: Ignition,
+    /// rotors of a wankel engine, sharing the collectors with the cylinders
+    pub rotors: Box<[Rotor]>,
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub intake_noise: Noise,
     pub intake_noise_factor: f32,
-    pub intake_noise_lp: LowPassFilter,
-    pub engine_vibration_filter: LowPassFilter,
+    pub intake_noise_lp: Filter,
+    /// air box, runners and throttle; without it the cylinders share a plain collector
+    pub intake: Option<Intake>,
+    pub engine_vibration_filter: Filter,
+    /// block, body panels and cabin, ringing with the vibrations
+    pub body: Option<Body>,
+    /// valvetrain, injectors, timing chain and alternator
+    pub mechanical: Option<Mechanical>,
     pub muffler: Muffler,
+    /// replaces the `muffler`, if set
+    pub exhaust: Option<Network>,
+    /// radiates the exhaust into the open
+    pub tailpipe: Option<Tailpipe>,
+    /// banks of cylinders with their own collectors and mufflers, replacing `muffler`, `exhaust` and `tailpipe`
+    pub banks: Box<[Bank]>,
+    pub crossover: Option<Crossover>,
     /// valve timing -0.5 - 0.5
     pub intake_valve_shift: f32,
     /// valve timing -0.5 - 0.5
     pub exhaust_valve_shift: f32,
     pub crankshaft_fluctuation: f32,
-    pub crankshaft_fluctuation_lp: LowPassFilter,
-    // #[serde(skip)]
+    pub crankshaft_fluctuation_lp: Filter,
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub crankshaft_noise: Noise,
     // running values
     /// crankshaft position, 0.0-1.0
-    // #[serde(skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub crankshaft_pos: f32,
-    // #[serde(skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub exhaust_collector: f32,
-    // #[serde(skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub intake_collector: f32,
 }
 
 impl Engine {
-    pub fn new(samples_per_second: u32) -> Self {
+    pub fn new(samples_per_second: u32) -> Result<Self, Error> {
         macro_rules! wave {
             ($delay:literal, $alpha:literal,$beta:literal) => {
                 WaveGuide::new(
@@ -66,19 +251,10 @@ impl Engine {
                     $alpha,
                     $beta,
                     samples_per_second,
-                )
+                )?
             };
         }
-        macro_rules! lpf {
-            ($len:literal) => {
-                LowPassFilter::new(1.0 / $len, samples_per_second)
-            };
-        }
-        Self {
-            rpm: 883.1155,
-            intake_volume: 0.32493597,
-            exhaust_volume: 0.63871837,
-            engine_vibrations_volume: 0.036345694,
+        Ok(Self {
             cylinders: vec![
                 Cylinder {
                     crank_offset: 0.0,
@@ -194,6 +370,32 @@ impl Engine {
                 },
             ]
             .into(),
+            ..Self::common(samples_per_second)?
+        })
+    }
+
+    /// Everything of `new` but the cylinders, shared with `rotary`.
+    fn common(samples_per_second: u32) -> Result<Self, Error> {
+        macro_rules! wave {
+            ($delay:literal, $alpha:literal,$beta:literal) => {
+                WaveGuide::new(
+                    ($delay * samples_per_second as f32) as usize,
+                    $alpha,
+                    $beta,
+                    samples_per_second,
+                )?
+            };
+        }
+        macro_rules! lpf {
+            ($len:literal) => {
+                Filter::from(LowPassFilter::new(1.0 / $len, samples_per_second)?)
+            };
+        }
+        Ok(Self {
+            rpm: 883.1155,
+            intake_volume: 0.32493597,
+            exhaust_volume: 0.63871837,
+            engine_vibrations_volume: 0.036345694,
             intake_noise_factor: 1.3716942,
             intake_noise_lp: lpf!(0.0005277371),
             engine_vibration_filter: lpf!(0.010829452),
@@ -212,7 +414,197 @@ impl Engine {
             crankshaft_fluctuation: 0.4000154,
             crankshaft_fluctuation_lp: lpf!(0.086017124),
             ..Default::default()
+        })
+    }
+
+    /// The state of every cylinder, at the current crankshaft position.
+    /// The rotors of a rotary engine follow the cylinders, see `Rotor::state`.
+    pub fn cylinder_states(&self) -> Vec<CylinderState> {
+        self.cylinders
+            .iter()
+            .map(|cylinder| {
+                let phase = (self.crankshaft_pos + cylinder.crank_offset).fract();
+                CylinderState {
+                    phase,
+                    intake_valve: intake_valve((phase + self.intake_valve_shift).fract()),
+                    exhaust_valve: exhaust_valve((phase + self.exhaust_valve_shift).fract()),
+                    ignition: self.ignition.pulse(phase, cylinder.ignition_time),
+                    level: cylinder.level,
+                }
+            })
+            .chain(self.rotors.iter().map(Rotor::state))
+            .collect()
+    }
+
+    pub fn collectors(&self) -> Collectors {
+        Collectors {
+            intake: self.intake_collector,
+            exhaust: if self.banks.is_empty() {
+                self.exhaust_collector
+            } else {
+                0.0
+            },
+            banks: self
+                .banks
+                .iter()
+                .map(|bank| bank.exhaust_collector)
+                .collect(),
+        }
+    }
+
+    /// Rebuilds all delay lines and filters for `samples_per_second`, eg. after deserializing.
+    /// Fails on a delay line too short for `samples_per_second`, or a network with an unknown pipe.
+    pub fn resample(&mut self, samples_per_second: u32) -> Result<(), Error> {
+        let sps = samples_per_second;
+        for cyl in self.cylinders.iter_mut() {
+            cyl.exhaust_waveguide.resample(sps)?;
+            cyl.intake_waveguide.resample(sps)?;
+            cyl.extractor_waveguide.resample(sps)?;
+        }
+        for rotor in self.rotors.iter_mut() {
+            rotor.exhaust_waveguide.resample(sps)?;
+            rotor.intake_waveguide.resample(sps)?;
+            rotor.extractor_waveguide.resample(sps)?;
+        }
+        self.intake_noise_lp.resample(sps);
+        if let Some(intake) = &mut self.intake {
+            intake.resample(sps)?;
         }
+        self.engine_vibration_filter.resample(sps);
+        if let Some(body) = &mut self.body {
+            body.resample(sps);
+        }
+        if let Some(mechanical) = &mut self.mechanical {
+            mechanical.resample(sps);
+        }
+        self.muffler.resample(sps)?;
+        if let Some(network) = &mut self.exhaust {
+            network.resample(sps)?;
+            network.resolve()?;
+        }
+        if let Some(tailpipe) = &mut self.tailpipe {
+            tailpipe.resample(sps)?;
+        }
+        for bank in self.banks.iter_mut() {
+            bank.muffler.resample(sps)?;
+            if let Some(tailpipe) = &mut bank.tailpipe {
+                tailpipe.resample(sps)?;
+            }
+            if let Some(network) = &mut bank.exhaust {
+                network.resample(sps)?;
+                network.resolve()?;
+            }
+        }
+        if let Some(Crossover::H { pipe }) = &mut self.crossover {
+            pipe.resample(sps)?;
+        }
+        self.crankshaft_fluctuation_lp.resample(sps);
+        Ok(())
+    }
+
+    /// Reads an engine from a config file, clamps what is out of range and resamples it to `samples_per_second`.
+    /// Returns what had to be clamped along with the engine.
+    #[cfg(feature = "serde")]
+    pub fn load<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+        samples_per_second: u32,
+    ) -> Result<(Self, Vec<Issue>), D::Error> {
+        use serde::de::Error as _;
+        let mut engine = <Self as serde::Deserialize>::deserialize(deserializer)?;
+        let issues = engine.validate(true);
+        check_rate(samples_per_second).map_err(D::Error::custom)?;
+        engine
+            .resample(samples_per_second)
+            .map_err(D::Error::custom)?;
+        Ok((engine, issues))
+    }
+
+    /// The engine of `new` with true dual exhaust: every other cylinder exhausts into the second bank,
+    /// and the banks are joined by an X-pipe.
+    pub fn dual_bank(samples_per_second: u32) -> Result<Self, Error> {
+        let mut engine = Self::new(samples_per_second)?;
+        for (i, cylinder) in engine.cylinders.iter_mut().enumerate() {
+            cylinder.bank = i % 2;
+        }
+        engine.banks = vec![
+            Bank::new(engine.muffler.clone(), -0.6),
+            Bank::new(engine.muffler.clone(), 0.6),
+        ]
+        .into();
+        engine.crossover = Some(Crossover::X { mix: 0.3 });
+        Ok(engine)
+    }
+
+    /// The engine of `new` as a turbo diesel, with compression ignition, a turbocharger and loud injectors.
+    pub fn diesel(samples_per_second: u32) -> Result<Self, Error> {
+        Self::new(samples_per_second)?.into_diesel(samples_per_second)
+    }
+
+    /// This engine as a turbo diesel, see `diesel`.
+    pub fn into_diesel(self, samples_per_second: u32) -> Result<Self, Error> {
+        let sps = samples_per_second;
+        let mut engine = Self {
+            rpm: 750.0,
+            ignition: Ignition::Compression {
+                rise: 0.08,
+                clatter: 0.6,
+                clatter_cycles: 14.0,
+            },
+            intake: Some(Intake::turbo_diesel(
+                self.cylinders.len() + self.rotors.len(),
+                sps,
+            )?),
+            mechanical: Some(Mechanical::diesel(sps)),
+            ..self
+        };
+        for cylinder in engine.cylinders.iter_mut() {
+            cylinder.ignition_factor *= 1.3;
+            cylinder.ignition_time = 0.08;
+        }
+        Ok(engine)
+    }
+
+    /// A two rotor wankel engine, in the style of the 13B.
+    pub fn rotary(samples_per_second: u32) -> Result<Self, Error> {
+        macro_rules! wave {
+            ($delay:literal, $alpha:literal,$beta:literal) => {
+                WaveGuide::new(
+                    ($delay * samples_per_second as f32) as usize,
+                    $alpha,
+                    $beta,
+                    samples_per_second,
+                )?
+            };
+        }
+        let rotor = |rotor_offset| -> Result<Rotor, Error> {
+            Ok(Rotor {
+                rotor_offset,
+                ports: PortTiming::SIDE,
+                exhaust_waveguide: wave!(0.0004791667, 0.6, 0.06),
+                intake_waveguide: wave!(0.00025, 1.0, -0.7575827),
+                extractor_waveguide: wave!(0.0007291667, 0.0, -0.00081294775),
+                intake_open_refl: 0.00607419,
+                intake_closed_refl: 1.0,
+                exhaust_open_refl: -0.00070154667,
+                exhaust_closed_refl: 0.6,
+                chamber_motion_factor: 1.8,
+                ignition_factor: 3.2,
+                ignition_time: 0.12,
+                housing_leak: 0.1,
+                ..Default::default()
+            })
+        };
+        Ok(Self {
+            rpm: 1000.0,
+            intake_volume: 0.6,
+            exhaust_volume: 1.6,
+            cylinders: Box::new([]),
+            // the rotors sit 180° apart on the eccentric shaft, which turns three times per rotor revolution
+            rotors: vec![rotor(0.0)?, rotor(1.0 / 6.0)?].into(),
+            intake_valve_shift: 0.0,
+            exhaust_valve_shift: 0.0,
+            ..Self::common(samples_per_second)?
+        })
     }
 }
 
@@ -236,7 +628,7 @@ impl Default for Noise {
 
 impl Noise {
     pub fn step(&mut self) -> f32 {
-        self.inner.next_u32() as f32 / (std::u32::MAX as f32 / 2.0) - 1.0
+        self.inner.next_u32() as f32 / (u32::MAX as f32 / 2.0) - 1.0
     }
 }
 
@@ -255,9 +647,12 @@ impl Noise {
 /// |EV|    - Exhaust valve modulation function for this side of the WaveGuide (alpha)
 /// ```
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Cylinder {
     /// offset of this cylinder's piston crank
     pub crank_offset: f32,
+    /// the `Bank` this cylinder exhausts into
+    pub bank: usize,
     /// waveguide from the cylinder to the exhaust
     pub exhaust_waveguide: WaveGuide,
     /// waveguide from the cylinder to the intake
@@ -276,10 +671,16 @@ pub struct Cylinder {
     pub ignition_time: f32,
 
     // running values
-    // #[serde(skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub cyl_sound: f32,
-    // #[serde(skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub extractor_exhaust: f32,
+    /// peak of what the cylinder puts into the exhaust collector, falling back over `LEVEL_RELEASE`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub level: f32,
+    /// the combustion pressure, part of `cyl_sound`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub ignition: f32,
 }
 
 impl Cylinder {
@@ -292,13 +693,12 @@ impl Cylinder {
         exhaust_collector: f32,
         intake_valve_shift: f32,
         exhaust_valve_shift: f32,
+        ignition: Ignition,
     ) -> (f32, f32, f32) {
         let crank = (crank_pos + self.crank_offset).fract();
 
-        self.cyl_sound = piston_motion(crank).madd(
-            self.piston_motion_factor,
-            fuel_ignition(crank, self.ignition_time) * self.ignition_factor,
-        );
+        self.ignition = ignition.pulse(crank, self.ignition_time) * self.ignition_factor;
+        self.cyl_sound = piston_motion(crank).madd(self.piston_motion_factor, self.ignition);
 
         let ex_valve = exhaust_valve((crank + exhaust_valve_shift).fract());
         let in_valve = intake_valve((crank + intake_valve_shift).fract());
@@ -331,45 +731,346 @@ impl Cylinder {
     }
 }
 
+/// What a cylinder is doing, to show it outside the sound.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CylinderState {
+    /// where the cylinder is in its cycle (0.0 - 1.0), the intake stroke starts at 0.0 and it fires at 0.5
+    pub phase: f32,
+    /// how far the intake valve is opened, 0.0 - 1.0
+    pub intake_valve: f32,
+    /// how far the exhaust valve is opened, 0.0 - 1.0
+    pub exhaust_valve: f32,
+    /// the combustion pressure, 0.0 while the cylinder isn't firing
+    pub ignition: f32,
+    /// see `Cylinder::level`
+    pub level: f32,
+}
+
+/// The pressures in the collectors, as of the last sample.
+#[derive(Clone, Debug, Default)]
+pub struct Collectors {
+    pub intake: f32,
+    /// the engine's exhaust collector, 0.0 if the cylinders exhaust into banks
+    pub exhaust: f32,
+    /// the exhaust collector of every `Bank`
+    pub banks: Vec<f32>,
+}
+
+#[derive(Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Ignition {
+    /// petrol, the pressure rises and falls smoothly
+    #[default]
+    Spark,
+    /// diesel, the pressure rises suddenly and the chamber rings with the knock
+    Compression {
+        /// fraction of the pulse spent rising, 0.0 - 1.0
+        rise: f32,
+        /// level of the knock
+        clatter: f32,
+        /// periods the knock rings during the pulse
+        clatter_cycles: f32,
+    },
+}
+
+impl Ignition {
+    /// the combustion pressure at `crank_pos`, starting at 0.5
+    pub fn pulse(self, crank_pos: f32, ignition_time: f32) -> f32 {
+        match self {
+            Self::Spark => fuel_ignition(crank_pos, ignition_time),
+            Self::Compression {
+                rise,
+                clatter,
+                clatter_cycles,
+            } => {
+                let x = (crank_pos - 0.5) / (ignition_time / 2.0);
+                if !(0.0..1.0).contains(&x) {
+                    return 0.0;
+                }
+                let pressure = if x < rise {
+                    x / rise
+                } else {
+                    let fall = (1.0 - x) / (1.0 - rise);
+                    fall * fall
+                };
+                let knock = (x * clatter_cycles * PI2F).sinf() * (-8.0 * x).exp();
+                knock.madd(clatter, pressure)
+            }
+        }
+    }
+}
+
+/// Port timing of a rotor housing, in fractions of a face's cycle (0.0 - 1.0).
+///
+/// A face goes through its cycle like a `Cylinder` goes through its crank cycle:
+/// intake starts at 0.0 and the mixture is ignited at 0.5.
+/// Opening points may lie below 0.0 or closing points above 1.0 to overlap the neighbouring stroke.
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PortTiming {
+    pub intake_open: f32,
+    pub intake_close: f32,
+    pub exhaust_open: f32,
+    pub exhaust_close: f32,
+}
+
+impl PortTiming {
+    /// Side intake ports, with little overlap.
+    pub const SIDE: Self = Self {
+        intake_open: 0.0,
+        intake_close: 0.27,
+        exhaust_open: 0.72,
+        exhaust_close: 1.0,
+    };
+    /// Peripheral intake ports, with a large overlap and the lumpy idle that comes with it.
+    pub const PERIPHERAL: Self = Self {
+        intake_open: -0.08,
+        intake_close: 0.3,
+        exhaust_open: 0.7,
+        exhaust_close: 1.04,
+    };
+}
+
+impl Default for PortTiming {
+    fn default() -> Self {
+        Self::SIDE
+    }
+}
+
+/// Represents one rotor of a wankel engine.
+///
+/// The rotor has three faces, each going through a full cycle once per rotor revolution.
+/// As the eccentric shaft turns three times per rotor revolution, every rotor fires once per shaft revolution.
+/// The ports are in the housing, so all faces share the rotor's waveguides, which are laid out like a `Cylinder`'s.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rotor {
+    /// offset of this rotor, in rotor revolutions (0.0 - 1.0)
+    pub rotor_offset: f32,
+    /// the `Bank` this rotor exhausts into
+    pub bank: usize,
+    pub ports: PortTiming,
+    /// waveguide from the exhaust port to the exhaust
+    pub exhaust_waveguide: WaveGuide,
+    /// waveguide from the intake port to the intake
+    pub intake_waveguide: WaveGuide,
+    /// waveguide from the other end of the exhaust WG to the exhaust collector
+    pub extractor_waveguide: WaveGuide,
+    // waveguide alpha values for when the ports are closed or opened
+    pub intake_open_refl: f32,
+    pub intake_closed_refl: f32,
+    pub exhaust_open_refl: f32,
+    pub exhaust_closed_refl: f32,
+
+    pub chamber_motion_factor: f32,
+    pub ignition_factor: f32,
+    /// the time it takes for the fuel to ignite in face cycles (0.0 - 1.0)
+    pub ignition_time: f32,
+    /// how much of a face's sound reaches the ports while it is sealed off from them
+    pub housing_leak: f32,
+
+    // running values
+    /// rotor position, 0.0-1.0
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub rotor_pos: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub last_crank_pos: f32,
+    /// how far the intake port is opened, summed over all faces
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub intake_port: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub rotor_sound: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub extractor_exhaust: f32,
+    /// the combustion pressure, summed over all faces
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub ignition: f32,
+    /// see `Cylinder::level`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub level: f32,
+}
+
+impl Rotor {
+    pub const FACES: usize = 3;
+
+    /// What the rotor is doing, with the ports as valves and the faces summed like in `pop`.
+    /// The phase is the one of its first face.
+    pub fn state(&self) -> CylinderState {
+        let mut state = CylinderState {
+            phase: (self.rotor_pos + self.rotor_offset).fract(),
+            level: self.level,
+            ..Default::default()
+        };
+        for face in 0..Self::FACES {
+            let pos =
+                (self.rotor_pos + self.rotor_offset + face as f32 / Self::FACES as f32).fract();
+            state.intake_valve += port(pos, self.ports.intake_open, self.ports.intake_close);
+            state.exhaust_valve += port(pos, self.ports.exhaust_open, self.ports.exhaust_close);
+            state.ignition += fuel_ignition(pos, self.ignition_time);
+        }
+        state.intake_valve = state.intake_valve.min(1.0);
+        state.exhaust_valve = state.exhaust_valve.min(1.0);
+        state
+    }
+
+    /// takes in the current exhaust collector pressure
+    /// returns (intake, exhaust, chambers + ignition)
+    #[inline]
+    fn pop(&mut self, crank_pos: f32, exhaust_collector: f32) -> (f32, f32, f32) {
+        // a crank cycle is two shaft revolutions, a rotor revolution is three
+        let mut delta = crank_pos - self.last_crank_pos;
+        delta -= delta.round();
+        self.last_crank_pos = crank_pos;
+        self.rotor_pos = delta.madd(2.0 / 3.0, self.rotor_pos).rem_euclid(1.0);
+
+        let mut vibration = 0.0;
+        let mut ex_port = 0.0;
+        self.intake_port = 0.0;
+        self.rotor_sound = 0.0;
+        self.ignition = 0.0;
+        for face in 0..Self::FACES {
+            let pos =
+                (self.rotor_pos + self.rotor_offset + face as f32 / Self::FACES as f32).fract();
+            let ignition = fuel_ignition(pos, self.ignition_time) * self.ignition_factor;
+            self.ignition += ignition;
+            let sound = piston_motion(pos).madd(self.chamber_motion_factor, ignition);
+            let ex = port(pos, self.ports.exhaust_open, self.ports.exhaust_close);
+            let int = port(pos, self.ports.intake_open, self.ports.intake_close);
+
+            ex_port += ex;
+            self.intake_port += int;
+            self.rotor_sound = sound.madd((ex + int).max(self.housing_leak), self.rotor_sound);
+            vibration += sound;
+        }
+
+        self.exhaust_waveguide.alpha = (self.exhaust_open_refl - self.exhaust_closed_refl)
+            .madd(ex_port.min(1.0), self.exhaust_closed_refl);
+        self.intake_waveguide.alpha = (self.intake_open_refl - self.intake_closed_refl)
+            .madd(self.intake_port.min(1.0), self.intake_closed_refl);
+
+        let ex_wg_ret = self.exhaust_waveguide.pop();
+        let in_wg_ret = self.intake_waveguide.pop();
+
+        let extractor_wg_ret = self.extractor_waveguide.pop();
+        self.extractor_exhaust = extractor_wg_ret.0;
+        self.extractor_waveguide
+            .push(ex_wg_ret.1, exhaust_collector);
+
+        (in_wg_ret.1, extractor_wg_ret.1, vibration)
+    }
+
+    /// called after pop
+    fn push(&mut self, intake: f32) {
+        let ex_in = (1.0 - self.exhaust_waveguide.alpha.abs()) * self.rotor_sound * 0.5;
+        self.exhaust_waveguide.push(ex_in, self.extractor_exhaust);
+        let in_in = (1.0 - self.intake_waveguide.alpha.abs()) * self.rotor_sound * 0.5;
+        self.intake_waveguide.push(in_in, intake);
+    }
+}
+
+/// One sample of every part of the engine's sound, before they are mixed.
+#[derive(Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stems {
+    pub intake: f32,
+    pub vibration: f32,
+    pub exhaust: f32,
+    /// gear whine of the `Transmission`
+    pub driveline: f32,
+    /// the roar of the `Intake`, already at its own volume
+    pub induction: f32,
+    /// the boom inside the cabin from the `Body`, already at its own volume
+    pub cabin: f32,
+    /// the ticking and whining of the `Mechanical` parts, already at its own volume
+    pub mechanical: f32,
+}
+
+impl Stems {
+    fn all(&self) -> [f32; 7] {
+        [
+            self.intake,
+            self.vibration,
+            self.exhaust,
+            self.driveline,
+            self.induction,
+            self.cabin,
+            self.mechanical,
+        ]
+    }
+
+    pub fn sum(&self) -> f32 {
+        self.all().iter().sum()
+    }
+
+    /// the sum of the stems, each multiplied by its gain in `gains`
+    pub fn weighted(&self, gains: &Self) -> f32 {
+        self.all()
+            .iter()
+            .zip(gains.all().iter())
+            .fold(0.0, |sum, (stem, gain)| stem.madd(*gain, sum))
+    }
+}
+
 pub struct Generator {
     pub volume: f32,
     pub samples_per_second: u32,
     pub engine: Engine,
-    /// `LowPassFilter` which is subtracted from the sample while playing back to reduce dc offset and thus clipping
-    dc_lp: LowPassFilter,
+    /// couples the engine to the wheels, if there are any
+    pub transmission: Option<Transmission>,
+    /// drives `engine.rpm` from the throttle, instead of setting it directly
+    pub dynamics: Option<Dynamics>,
+    /// balances the stems for where the engine is heard from, instead of mixing them evenly
+    pub listener: Option<Listener>,
+    /// processes the mix, in order
+    pub effects: Vec<Effect>,
+    /// keeps the output below its ceiling, after everything else
+    pub limiter: Option<Limiter>,
+    /// records what happens in the engine, if set
+    pub events: Option<Events>,
+    /// follows the vibrations for a gamepad's motors, if set
+    pub rumble: Option<Rumble>,
+    /// highpass applied to the sample while playing back to reduce dc offset and thus clipping, eg. a `DcBlocker`
+    dc_filter: Filter,
 }
 
 impl Generator {
-    pub fn new(samples_per_second: u32, engine: Engine, dc_lp: LowPassFilter) -> Self {
-        Self {
+    pub fn new(samples_per_second: u32, engine: Engine, dc_filter: Filter) -> Result<Self, Error> {
+        check_rate(samples_per_second)?;
+        if engine.cylinders.is_empty() && engine.rotors.is_empty() {
+            return Err(Error::NoCylinders);
+        }
+        for (name, value) in [
+            ("rpm", engine.rpm),
+            ("intake_volume", engine.intake_volume),
+            ("exhaust_volume", engine.exhaust_volume),
+            ("engine_vibrations_volume", engine.engine_vibrations_volume),
+            ("intake_noise_factor", engine.intake_noise_factor),
+            ("intake_valve_shift", engine.intake_valve_shift),
+            ("exhaust_valve_shift", engine.exhaust_valve_shift),
+            ("crankshaft_fluctuation", engine.crankshaft_fluctuation),
+        ] {
+            check_finite(name, value)?;
+        }
+        Ok(Self {
             volume: 0.1_f32,
             samples_per_second,
             engine,
-            dc_lp,
-        }
+            transmission: None,
+            dynamics: None,
+            listener: None,
+            effects: Vec::new(),
+            limiter: None,
+            events: None,
+            rumble: None,
+            dc_filter,
+        })
     }
 
     #[cfg(feature = "godot")]
     pub fn generate(&mut self, player: &mut Gd<AudioStreamGeneratorPlayback>) {
-        let samples_per_second = self.samples_per_second as f32 * 120.0;
-
-        let inc = self.engine.rpm / samples_per_second;
-
         for _ in 0..player.get_frames_available() {
-            self.engine.crankshaft_pos = (self.engine.crankshaft_pos + inc).fract();
-
-            let (intake, vibration, exhaust) = self.gen();
-            let mixed = exhaust.madd(
-                self.engine.exhaust_volume,
-                intake.madd(
-                    self.engine.intake_volume,
-                    vibration * self.engine.engine_vibrations_volume,
-                ),
-            ) * self.volume;
-
-            // reduces dc offset
-            let sample = mixed - self.dc_lp.filter(mixed);
-            player.push_frame(Vector2::splat(sample));
+            let (left, right) = self.stereo_frame();
+            player.push_frame(Vector2::new(left, right));
         }
     }
 
@@ -386,6 +1087,25 @@ impl Generator {
 
             cyl.extractor_exhaust = 0.0;
             cyl.cyl_sound = 0.0;
+            cyl.level = 0.0;
+            cyl.ignition = 0.0;
+        }
+
+        for rotor in self.engine.rotors.iter_mut() {
+            [
+                &mut rotor.exhaust_waveguide,
+                &mut rotor.intake_waveguide,
+                &mut rotor.extractor_waveguide,
+            ]
+            .iter_mut()
+            .flat_map(|x| [&mut x.chamber0, &mut x.chamber1])
+            .for_each(|chamber| chamber.samples.data.iter_mut().for_each(|x| *x = 0.0));
+
+            rotor.extractor_exhaust = 0.0;
+            rotor.rotor_sound = 0.0;
+            rotor.intake_port = 0.0;
+            rotor.ignition = 0.0;
+            rotor.level = 0.0;
         }
 
         std::iter::once(&mut self.engine.muffler.straight_pipe)
@@ -407,29 +1127,172 @@ impl Generator {
                 .for_each(|sample| *sample = 0.0);
         }
 
+        if let Some(network) = &mut self.engine.exhaust {
+            network.clear();
+        }
+        if let Some(tailpipe) = &mut self.engine.tailpipe {
+            tailpipe.clear();
+        }
+        for bank in self.engine.banks.iter_mut() {
+            bank.muffler.clear();
+            if let Some(tailpipe) = &mut bank.tailpipe {
+                tailpipe.clear();
+            }
+            if let Some(network) = &mut bank.exhaust {
+                network.clear();
+            }
+            bank.exhaust_collector = 0.0;
+            bank.output = 0.0;
+        }
+        if let Some(Crossover::H { pipe }) = &mut self.engine.crossover {
+            [&mut pipe.chamber0, &mut pipe.chamber1]
+                .iter_mut()
+                .for_each(|chamber| chamber.samples.data.iter_mut().for_each(|x| *x = 0.0));
+        }
+
+        if let Some(intake) = &mut self.engine.intake {
+            intake
+                .runners
+                .iter_mut()
+                .chain(std::iter::once(&mut intake.snorkel))
+                .flat_map(|x| [&mut x.chamber0, &mut x.chamber1])
+                .for_each(|chamber| chamber.samples.data.iter_mut().for_each(|x| *x = 0.0));
+            intake.airbox.clear();
+            intake.ports.iter_mut().for_each(|port| *port = 0.0);
+        }
+        if let Some(body) = &mut self.engine.body {
+            body.clear();
+        }
+        if let Some(mechanical) = &mut self.engine.mechanical {
+            mechanical.clear();
+        }
+        if let Some(listener) = &mut self.listener {
+            listener.clear();
+        }
+        self.effects.iter_mut().for_each(Effect::clear);
+        if let Some(limiter) = &mut self.limiter {
+            limiter.clear();
+        }
+        if let Some(events) = &mut self.events {
+            events.clear();
+        }
+        if let Some(rumble) = &mut self.rumble {
+            rumble.clear();
+        }
+
         self.engine.exhaust_collector = 0.0;
         self.engine.intake_collector = 0.0;
     }
 
+    /// the engine speed after coupling it to the wheels
+    pub fn rpm(&self) -> f32 {
+        self.transmission
+            .as_ref()
+            .map_or(self.engine.rpm, |transmission| transmission.rpm)
+    }
+
     pub fn frame(&mut self) -> f32 {
-        let inc = self.engine.rpm / (self.samples_per_second as f32 * 120.0);
-        self.engine.crankshaft_pos = (self.engine.crankshaft_pos + inc).fract();
-        let (intake, vibration, exhaust) = self.gen();
-        let mixed = exhaust.madd(
-            self.engine.exhaust_volume,
-            intake.madd(
-                self.engine.intake_volume,
-                vibration * self.engine.engine_vibrations_volume,
-            ),
-        ) * self.volume;
+        let mixed = self.mix();
+        match &mut self.limiter {
+            Some(limiter) => limiter.process(mixed, mixed).0,
+            None => mixed,
+        }
+    }
 
+    /// Like `frame`, but with the exhaust of every `Bank` placed at its `pan`.
+    /// returns `(left, right)`
+    pub fn stereo_frame(&mut self) -> (f32, f32) {
+        let (left, right) = self.stereo_mix();
+        match &mut self.limiter {
+            Some(limiter) => limiter.process(left, right),
+            None => (left, right),
+        }
+    }
+
+    /// the mix, before the limiter
+    fn mix(&mut self) -> f32 {
+        let stems = self.stems();
+        let mixed = match &mut self.listener {
+            Some(listener) => listener.hear(&stems),
+            None => stems.sum(),
+        } * self.volume;
         // reduces dc offset
-        mixed - self.dc_lp.filter(mixed)
+        let mixed = self.dc_filter.filter(mixed);
+        self.effects
+            .iter_mut()
+            .fold(mixed, |sample, effect| effect.process(sample))
+    }
+
+    /// the panned mix, before the limiter
+    fn stereo_mix(&mut self) -> (f32, f32) {
+        let stems = self.stems();
+        let (mut left, mut right) = (stems, stems);
+        for bank in self.engine.banks.iter() {
+            // equal power panning, unchanged in the center
+            let angle = (bank.pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+            let centered = bank.output * self.engine.exhaust_volume;
+            left.exhaust = centered.madd(angle.cosf().madd(SQRT_2, -1.0), left.exhaust);
+            right.exhaust = centered.madd(angle.sinf().madd(SQRT_2, -1.0), right.exhaust);
+        }
+        let (left, right) = match &mut self.listener {
+            Some(listener) => listener.hear_stereo(&left, &right),
+            None => (left.sum(), right.sum()),
+        };
+        let (left, right) = (left * self.volume, right * self.volume);
+        // reduces dc offset
+        let (left, right) = self.dc_filter.filter_stereo(left, right);
+        self.effects
+            .iter_mut()
+            .fold((left, right), |(left, right), effect| {
+                effect.process_stereo(left, right)
+            })
+    }
+
+    /// the stems of the next sample, at the engine's volumes
+    fn stems(&mut self) -> Stems {
+        let dt = 1.0 / self.samples_per_second as f32;
+        let (rpm, driveline) = match (&mut self.dynamics, &mut self.transmission) {
+            (Some(dynamics), Some(transmission)) => {
+                let driveline = transmission.advance(self.samples_per_second);
+                transmission.rpm = dynamics.step(dt, transmission.load());
+                self.engine.rpm = transmission.rpm;
+                (transmission.rpm, driveline)
+            }
+            (Some(dynamics), None) => {
+                self.engine.rpm = dynamics.step(dt, Load::default());
+                (self.engine.rpm, 0.0)
+            }
+            (None, Some(transmission)) => {
+                transmission.step(self.engine.rpm, self.samples_per_second)
+            }
+            (None, None) => (self.engine.rpm, 0.0),
+        };
+        let inc = rpm / (self.samples_per_second as f32 * 120.0);
+        let last_crank_pos = self.engine.crankshaft_pos;
+        self.engine.crankshaft_pos = (last_crank_pos + inc).fract();
+        if let Some(events) = &mut self.events {
+            events.record(
+                &self.engine,
+                self.dynamics.as_ref(),
+                last_crank_pos,
+                rpm,
+                self.samples_per_second,
+            );
+        }
+        let stems = Stems {
+            driveline,
+            ..self.gen()
+        };
+        Stems {
+            intake: stems.intake * self.engine.intake_volume,
+            vibration: stems.vibration * self.engine.engine_vibrations_volume,
+            exhaust: stems.exhaust * self.engine.exhaust_volume,
+            ..stems
+        }
     }
 
     /// generates one sample worth of audio
-    /// returns  `(intake, engine vibrations, exhaust, waveguides dampened)`
-    fn gen(&mut self) -> (f32, f32, f32) {
+    fn gen(&mut self) -> Stems {
         let intake_noise = self
             .engine
             .intake_noise_lp
@@ -438,82 +1301,183 @@ impl Generator {
 
         let mut engine_vibration = 0.0;
 
-        let num_cyl = self.engine.cylinders.len() as f32;
+        let num_cyl = (self.engine.cylinders.len() + self.engine.rotors.len()) as f32;
 
         let last_exhaust_collector = self.engine.exhaust_collector / num_cyl;
         self.engine.exhaust_collector = 0.0;
+        for bank in self.engine.banks.iter_mut() {
+            bank.last_exhaust_collector = bank.exhaust_collector / bank.cylinders.max(1.0);
+            bank.exhaust_collector = 0.0;
+            bank.cylinders = 0.0;
+        }
         self.engine.intake_collector = 0.0;
 
         let crankshaft_fluctuation_offset = self
             .engine
             .crankshaft_fluctuation_lp
             .filter(self.engine.crankshaft_noise.step());
+        let level_fall = 1.0 - 1.0 / (LEVEL_RELEASE * self.samples_per_second as f32);
 
-        for cylinder in self.engine.cylinders.iter_mut() {
+        if let Some(intake) = &mut self.engine.intake {
+            intake.ports.iter_mut().for_each(|port| *port = 0.0);
+        }
+        for (i, cylinder) in self.engine.cylinders.iter_mut().enumerate() {
             let (cyl_intake, cyl_exhaust, cyl_vib) = cylinder.pop(
                 self.engine
                     .crankshaft_fluctuation
                     .madd(crankshaft_fluctuation_offset, self.engine.crankshaft_pos),
-                last_exhaust_collector,
+                collector(&self.engine.banks, last_exhaust_collector, cylinder.bank),
                 self.engine.intake_valve_shift,
                 self.engine.exhaust_valve_shift,
+                self.engine.ignition,
             );
 
             self.engine.intake_collector += cyl_intake;
-            self.engine.exhaust_collector += cyl_exhaust;
+            if let Some(intake) = &mut self.engine.intake {
+                intake.feed(i, cyl_intake);
+            }
+            collect(
+                &mut self.engine.banks,
+                &mut self.engine.exhaust_collector,
+                cylinder.bank,
+                cyl_exhaust,
+            );
+            cylinder.level = cyl_exhaust.abs().max(cylinder.level * level_fall);
 
             engine_vibration += cyl_vib;
         }
 
-        // parallel input to the exhaust straight pipe
-        // alpha end is at exhaust collector
-        let straight_pipe_wg_ret = self.engine.muffler.straight_pipe.pop();
+        let num_cylinders = self.engine.cylinders.len();
+        for (i, rotor) in self.engine.rotors.iter_mut().enumerate() {
+            let (rotor_intake, rotor_exhaust, rotor_vib) = rotor.pop(
+                self.engine
+                    .crankshaft_fluctuation
+                    .madd(crankshaft_fluctuation_offset, self.engine.crankshaft_pos),
+                collector(&self.engine.banks, last_exhaust_collector, rotor.bank),
+            );
 
-        // alpha end is at straight pipe end (beta)
-        let mut muffler_wg_ret = (0.0, 0.0);
+            self.engine.intake_collector += rotor_intake;
+            if let Some(intake) = &mut self.engine.intake {
+                intake.feed(num_cylinders + i, rotor_intake);
+            }
+            collect(
+                &mut self.engine.banks,
+                &mut self.engine.exhaust_collector,
+                rotor.bank,
+                rotor_exhaust,
+            );
+            rotor.level = rotor_exhaust.abs().max(rotor.level * level_fall);
 
-        for muffler_line in self.engine.muffler.muffler_elements.iter_mut() {
-            let ret = muffler_line.pop();
-            muffler_wg_ret.0 += ret.0;
-            muffler_wg_ret.1 += ret.1;
+            engine_vibration += rotor_vib;
         }
 
         // pop  //
         //////////
         // push //
 
-        for cylinder in self.engine.cylinders.iter_mut() {
+        // the speed the crankshaft turns at, coupled to the wheels while in gear
+        let rpm = self.rpm();
+        let (intake_noise, induction) = match &mut self.engine.intake {
+            Some(intake) => {
+                let (plate, induction) = intake.process(num_cyl, rpm, self.samples_per_second);
+                (intake_noise + plate, induction)
+            }
+            None => (intake_noise, 0.0),
+        };
+        let plenum = self.engine.intake_collector / num_cyl;
+        let intake = self.engine.intake.as_ref();
+        let returned = |i| intake.map_or(plenum, |intake| intake.returned(i));
+
+        for (i, cylinder) in self.engine.cylinders.iter_mut().enumerate() {
             // modulate intake
             cylinder.push(intake_noise.madd(
                 intake_valve((self.engine.crankshaft_pos + cylinder.crank_offset).fract()),
-                self.engine.intake_collector / num_cyl,
+                returned(i),
             ));
         }
 
-        self.engine
-            .muffler
-            .straight_pipe
-            .push(self.engine.exhaust_collector, muffler_wg_ret.0);
-
-        self.engine.exhaust_collector += straight_pipe_wg_ret.0;
-
-        let muffler_elements = self.engine.muffler.muffler_elements.len() as f32;
-
-        for muffler_delay_line in self.engine.muffler.muffler_elements.iter_mut() {
-            muffler_delay_line.push(straight_pipe_wg_ret.1 / muffler_elements, 0.0);
+        for (i, rotor) in self.engine.rotors.iter_mut().enumerate() {
+            rotor.push(intake_noise.madd(rotor.intake_port.min(1.0), returned(num_cylinders + i)));
         }
 
-        engine_vibration = self.engine.engine_vibration_filter.filter(engine_vibration);
+        let exhaust = if self.engine.banks.is_empty() {
+            let returning = self
+                .engine
+                .tailpipe
+                .as_ref()
+                .map_or(0.0, |tailpipe| tailpipe.returning);
+            let (back_pressure, exhaust) = match &mut self.engine.exhaust {
+                Some(network) => network.process(self.engine.exhaust_collector, returning),
+                None => self
+                    .engine
+                    .muffler
+                    .process(self.engine.exhaust_collector, returning),
+            };
+            self.engine.exhaust_collector += back_pressure;
+            match &mut self.engine.tailpipe {
+                Some(tailpipe) => tailpipe.process(exhaust),
+                None => exhaust,
+            }
+        } else {
+            if let (Some(crossover), [a, b, ..]) =
+                (&mut self.engine.crossover, &mut *self.engine.banks)
+            {
+                crossover.couple(&mut a.exhaust_collector, &mut b.exhaust_collector);
+            }
+            let mut exhaust = 0.0;
+            for bank in self.engine.banks.iter_mut() {
+                bank.process();
+                exhaust += bank.output;
+            }
+            exhaust
+        };
+
+        let (bay, cabin) = match &mut self.engine.body {
+            Some(body) => body.process(engine_vibration),
+            None => (0.0, 0.0),
+        };
+        let vibration = self.engine.engine_vibration_filter.filter(engine_vibration);
+        if let Some(rumble) = &mut self.rumble {
+            let ignition = self
+                .engine
+                .cylinders
+                .iter()
+                .map(|cylinder| cylinder.ignition)
+                .chain(self.engine.rotors.iter().map(|rotor| rotor.ignition))
+                .sum::<f32>()
+                / num_cyl;
+            rumble.process(vibration / num_cyl, ignition, self.samples_per_second);
+        }
+        engine_vibration = vibration + bay;
 
-        (
-            self.engine.intake_collector,
-            engine_vibration,
-            muffler_wg_ret.1,
-        )
+        let mechanical = match &mut self.engine.mechanical {
+            Some(mechanical) => mechanical.process(
+                self.engine.crankshaft_pos,
+                rpm,
+                self.engine
+                    .cylinders
+                    .iter()
+                    .map(|cylinder| cylinder.crank_offset),
+                &self.engine.rotors,
+                self.samples_per_second,
+            ),
+            None => 0.0,
+        };
+
+        Stems {
+            intake: self.engine.intake_collector,
+            vibration: engine_vibration,
+            exhaust,
+            induction,
+            cabin,
+            mechanical,
+            ..Default::default()
+        }
     }
 }
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WaveGuide {
     // goes from x0 to x1
     pub chamber0: DelayLine,
@@ -525,23 +1489,39 @@ pub struct WaveGuide {
     pub beta: f32,
 
     // running values
-    // #[serde(skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
     c1_out: f32,
-    // #[serde(skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
     c0_out: f32,
 }
 
 impl WaveGuide {
+    pub fn resample(&mut self, samples_per_second: u32) -> Result<(), Error> {
+        self.chamber0.resample(samples_per_second)?;
+        self.chamber1.resample(samples_per_second)
+    }
+
     #[inline]
-    pub fn new(delay: usize, alpha: f32, beta: f32, samples_per_second: u32) -> Self {
-        Self {
+    pub fn new(
+        delay: usize,
+        alpha: f32,
+        beta: f32,
+        samples_per_second: u32,
+    ) -> Result<Self, Error> {
+        check_rate(samples_per_second)?;
+        if delay == 0 {
+            return Err(Error::EmptyDelay);
+        }
+        check_finite("alpha", alpha)?;
+        check_finite("beta", beta)?;
+        Ok(Self {
             chamber0: DelayLine::new(delay, samples_per_second),
             chamber1: DelayLine::new(delay, samples_per_second),
             alpha,
             beta,
             c1_out: 0.0,
             c0_out: 0.0,
-        }
+        })
     }
 
     #[inline]
@@ -579,12 +1559,13 @@ impl WaveGuide {
 }
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LoopBuffer {
     // in seconds
     pub delay: f32,
-    // #[serde(skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub data: Box<[f32]>,
-    // #[serde(skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub pos: usize,
 }
 
@@ -599,6 +1580,17 @@ impl LoopBuffer {
         }
     }
 
+    /// Rebuilds the buffer with the same `delay` at `samples_per_second`. Clears the buffer.
+    /// A delay that rounds to no samples at all is refused, and the buffer is left as it was.
+    pub fn resample(&mut self, samples_per_second: u32) -> Result<(), Error> {
+        let len = (self.delay * samples_per_second as f32).round();
+        if len.is_nan() || len < 1.0 {
+            return Err(Error::EmptyDelay);
+        }
+        *self = Self::new(len as usize, samples_per_second);
+        Ok(())
+    }
+
     /// Sets the value at the current position. Must be called with `pop`.
     /// ```rust
     /// let mut lb = LoopBuffer::new(2);
@@ -627,39 +1619,86 @@ impl LoopBuffer {
 }
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LowPassFilter {
     /// 1 / cutoff frequency
     pub delay: f32,
-    // #[serde(skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub alpha: f32,
-    // #[serde(skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub last: f32,
+    /// the `last` of the right channel, for `filter_stereo`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub right: f32,
 }
 
 impl LowPassFilter {
-    pub fn new(freq: f32, samples_per_second: u32) -> Self {
+    pub fn new(freq: f32, samples_per_second: u32) -> Result<Self, Error> {
+        check_rate(samples_per_second)?;
+        check_finite("freq", freq)?;
+        Ok(Self::at(freq, samples_per_second))
+    }
+
+    fn at(freq: f32, samples_per_second: u32) -> Self {
         Self {
             delay: 1.0 / freq,
             alpha: (PI2F * (1.0 / samples_per_second as f32) * freq)
                 / (PI2F * (1.0 / samples_per_second as f32)).madd(freq, 1.0),
             last: 0.0,
+            right: 0.0,
         }
     }
 
+    pub fn resample(&mut self, samples_per_second: u32) {
+        *self = Self::at(1.0 / self.delay, samples_per_second);
+    }
+
+    /// Moves the cutoff to `freq`, keeping the filter's state so it doesn't click.
+    pub fn retune(&mut self, freq: f32, samples_per_second: u32) -> Result<(), Error> {
+        check_rate(samples_per_second)?;
+        check_finite("freq", freq)?;
+        *self = Self {
+            last: self.last,
+            right: self.right,
+            ..Self::at(freq, samples_per_second)
+        };
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.last = 0.0;
+        self.right = 0.0;
+    }
+
     #[inline]
     pub fn filter(&mut self, sample: f32) -> f32 {
         let ret = (sample - self.last).madd(self.alpha, self.last);
         self.last = ret;
         ret
     }
+
+    /// `filter` for the left and right channel, the right one runs on `right`
+    #[inline]
+    pub fn filter_stereo(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let left = self.filter(left);
+        std::mem::swap(&mut self.last, &mut self.right);
+        let right = self.filter(right);
+        std::mem::swap(&mut self.last, &mut self.right);
+        (left, right)
+    }
 }
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DelayLine {
     pub samples: LoopBuffer,
 }
 
 impl DelayLine {
+    pub fn resample(&mut self, samples_per_second: u32) -> Result<(), Error> {
+        self.samples.resample(samples_per_second)
+    }
+
     pub fn new(delay: usize, samples_per_second: u32) -> Self {
         Self {
             samples: LoopBuffer::new(delay, samples_per_second),
@@ -691,6 +1730,17 @@ fn intake_valve(crank_pos: f32) -> f32 {
     }
 }
 
+/// opening of a port between `open` and `close`, like `intake_valve` and `exhaust_valve`
+fn port(pos: f32, open: f32, close: f32) -> f32 {
+    let len = close - open;
+    let t = (pos - open).rem_euclid(1.0);
+    if t < len {
+        (std::f32::consts::PI * t / len).sinf()
+    } else {
+        0.0
+    }
+}
+
 fn piston_motion(crank_pos: f32) -> f32 {
     (crank_pos * PI4F).cosf()
 }
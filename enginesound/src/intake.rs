@@ -0,0 +1,224 @@
+//! ## Intake module ##
+//!
+//! The path the air takes into the engine:
+//! ```
+//! (atmosphere) <==snorkel==> (air box) <==runners==> (plenum) <==> cylinders
+//! ```
+//! The induction roar is what comes out of the snorkel.
+//! With individual throttle bodies there is no air box and no plenum, every runner ends in its own trumpet.
+//! A turbocharger adds its whistle to the roar.
+//!
+#![warn(clippy::suboptimal_flops, clippy::use_self, clippy::dbg_macro)]
+use crate::error::Error;
+use crate::filter::Biquad;
+use crate::gen::{LowPassFilter, Noise, WaveGuide, PI2F};
+use crate::utils::FExt;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Intake {
+    /// the air box's helmholtz resonance
+    pub airbox: Biquad,
+    /// how much of the resonance is added to the air box pressure
+    pub resonance: f32,
+    /// waveguides from the cylinders (alpha) to the air box (beta), in parallel, one per cylinder
+    pub runners: Box<[WaveGuide]>,
+    /// waveguide from the air box (alpha) to the atmosphere (beta)
+    pub snorkel: WaveGuide,
+    /// how far the throttle plate is opened, 0.0 - 1.0
+    pub throttle: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub throttle_noise: Noise,
+    pub throttle_noise_lp: LowPassFilter,
+    /// the noise of the air rushing past the throttle plate, at full throttle
+    pub throttle_noise_factor: f32,
+    /// every runner has its own throttle and trumpet, instead of sharing a plenum and an air box
+    pub individual_throttle_bodies: bool,
+    /// volume of the induction roar
+    pub volume: f32,
+    /// whistles in the snorkel
+    pub turbo: Option<Turbo>,
+
+    // running values
+    /// the pressure at each runner's cylinder end: what the cylinders `feed` in before `process`,
+    /// what is `returned` to them after
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub ports: Box<[f32]>,
+}
+
+/// A turbocharger, heard as a whistle and a hiss rising with the boost.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Turbo {
+    /// time the turbo takes to spool up, in s
+    pub lag: f32,
+    /// the engine speed at which an open throttle gives full boost
+    pub boost_rpm: f32,
+    /// frequency of the whistle at full boost, in Hz
+    pub whistle_freq: f32,
+    pub whistle_level: f32,
+    /// level of the air rushing through the compressor at full boost
+    pub hiss_level: f32,
+    pub hiss_lp: LowPassFilter,
+
+    // running values
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub noise: Noise,
+    /// 0.0 - 1.0 of full boost
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub spool: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub phase: f32,
+}
+
+impl Turbo {
+    pub fn new(samples_per_second: u32) -> Result<Self, Error> {
+        Ok(Self {
+            lag: 0.8,
+            boost_rpm: 1800.0,
+            whistle_freq: 7000.0,
+            whistle_level: 0.05,
+            hiss_level: 0.15,
+            hiss_lp: LowPassFilter::new(3000.0, samples_per_second)?,
+            noise: Noise::default(),
+            spool: 0.0,
+            phase: 0.0,
+        })
+    }
+
+    fn process(&mut self, throttle: f32, rpm: f32, samples_per_second: u32) -> f32 {
+        let sps = samples_per_second as f32;
+        let target = throttle.clamp(0.0, 1.0) * (rpm / self.boost_rpm).clamp(0.0, 1.0);
+        let follow = 1.0 - (-1.0 / (self.lag * sps)).exp();
+        self.spool = (target - self.spool).madd(follow, self.spool);
+
+        self.phase = (self.whistle_freq * self.spool / sps + self.phase).fract();
+        let whistle = (self.phase * PI2F).sinf() * self.spool * self.spool;
+        let hiss = self.hiss_lp.filter(self.noise.step()) * self.spool;
+        whistle.madd(self.whistle_level, hiss * self.hiss_level)
+    }
+}
+
+impl Intake {
+    /// A ten litre air box behind a short snorkel, with a runner for each of the `cylinders`.
+    pub fn new(cylinders: usize, samples_per_second: u32) -> Result<Self, Error> {
+        macro_rules! wave {
+            ($delay:literal, $alpha:literal,$beta:literal) => {
+                WaveGuide::new(
+                    ($delay * samples_per_second as f32) as usize,
+                    $alpha,
+                    $beta,
+                    samples_per_second,
+                )?
+            };
+        }
+        Ok(Self {
+            airbox: Biquad::helmholtz(0.01, 0.005, 0.15, 4.0, samples_per_second),
+            resonance: 2.0,
+            // four slightly different lengths, so the runners don't all resonate together
+            runners: (0..cylinders.max(1))
+                .map(|i| {
+                    let delay = 0.0000208333_f32.madd((i % 4) as f32, 0.0007291667);
+                    WaveGuide::new(
+                        (delay * samples_per_second as f32) as usize,
+                        0.0,
+                        0.2,
+                        samples_per_second,
+                    )
+                })
+                .collect::<Result<_, _>>()?,
+            snorkel: wave!(0.0010416667, 0.1, -0.3),
+            throttle: 0.0,
+            throttle_noise: Noise::default(),
+            throttle_noise_lp: LowPassFilter::new(2000.0, samples_per_second)?,
+            throttle_noise_factor: 0.4,
+            individual_throttle_bodies: false,
+            volume: 0.3,
+            turbo: None,
+            ports: vec![0.0; cylinders.max(1)].into(),
+        })
+    }
+
+    /// A large air box behind a turbocharger, without a throttle plate.
+    pub fn turbo_diesel(cylinders: usize, samples_per_second: u32) -> Result<Self, Error> {
+        Ok(Self {
+            airbox: Biquad::helmholtz(0.02, 0.006, 0.25, 3.0, samples_per_second),
+            throttle_noise_factor: 0.05,
+            turbo: Some(Turbo::new(samples_per_second)?),
+            ..Self::new(cylinders, samples_per_second)?
+        })
+    }
+
+    pub fn resample(&mut self, samples_per_second: u32) -> Result<(), Error> {
+        self.airbox.resample(samples_per_second);
+        for runner in self.runners.iter_mut() {
+            runner.resample(samples_per_second)?;
+        }
+        self.snorkel.resample(samples_per_second)?;
+        self.throttle_noise_lp.resample(samples_per_second);
+        if let Some(turbo) = &mut self.turbo {
+            turbo.hiss_lp.resample(samples_per_second);
+        }
+        self.ports = vec![0.0; self.runners.len().max(1)].into();
+        Ok(())
+    }
+
+    /// Feeds the intake side of cylinder `i` into its runner, before `process`.
+    /// With more cylinders than runners, they wrap around and share them.
+    pub fn feed(&mut self, i: usize, intake: f32) {
+        let len = self.ports.len();
+        self.ports[i % len] += intake;
+    }
+
+    /// the pressure `process` returned to the intake side of cylinder `i`
+    pub fn returned(&self, i: usize) -> f32 {
+        self.ports[i % self.ports.len()]
+    }
+
+    /// takes in what the cylinders `feed` in, and leaves the pressure they get back to be `returned`
+    /// returns `(throttle noise, induction roar)`, the plenum pressure is divided among `cylinders`
+    pub fn process(&mut self, cylinders: f32, rpm: f32, samples_per_second: u32) -> (f32, f32) {
+        let turbo = match &mut self.turbo {
+            Some(turbo) => turbo.process(self.throttle, rpm, samples_per_second),
+            None => 0.0,
+        };
+        let plate = self.throttle_noise_lp.filter(self.throttle_noise.step())
+            * self.throttle_noise_factor
+            * self.throttle.clamp(0.0, 1.0);
+
+        if self.individual_throttle_bodies {
+            // the trumpets are open to the atmosphere, they radiate directly
+            // without a plenum, every cylinder only gets what its own runner reflects
+            let mut trumpets = 0.0;
+            for (runner, port) in self.runners.iter_mut().zip(self.ports.iter_mut()) {
+                let ret = runner.pop();
+                runner.push(*port, 0.0);
+                *port = ret.0;
+                trumpets += ret.1;
+            }
+            return (plate, trumpets.madd(self.volume, turbo));
+        }
+
+        let mut runner_wg_ret = (0.0, 0.0);
+        for runner in self.runners.iter_mut() {
+            let ret = runner.pop();
+            runner_wg_ret.0 += ret.0;
+            runner_wg_ret.1 += ret.1;
+        }
+        let runners = self.runners.len().max(1) as f32;
+        let intake_collector: f32 = self.ports.iter().sum();
+
+        let snorkel_wg_ret = self.snorkel.pop();
+        let pressure = runner_wg_ret.1 + snorkel_wg_ret.0;
+        let airbox = self.airbox.filter(pressure).madd(self.resonance, pressure);
+
+        for runner in self.runners.iter_mut() {
+            runner.push(intake_collector / runners, airbox / runners);
+        }
+        self.snorkel.push(airbox, 0.0);
+
+        // the cylinders share the plenum
+        let plenum = (intake_collector + runner_wg_ret.0) / cylinders;
+        self.ports.iter_mut().for_each(|port| *port = plenum);
+
+        (plate, snorkel_wg_ret.1.madd(self.volume, turbo))
+    }
+}
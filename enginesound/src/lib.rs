@@ -1,8 +1,24 @@
-#![feature(once_cell_get_mut)]
+pub mod body;
+#[cfg(feature = "godot")]
+pub mod config;
+pub mod driveline;
+pub mod dynamics;
+pub mod effects;
+pub mod electric;
+pub mod error;
+pub mod events;
+pub mod filter;
 pub mod gen;
+pub mod intake;
+pub mod listener;
+pub mod mechanical;
+pub mod network;
 #[cfg(feature = "godot")]
 pub mod node;
+pub mod rumble;
+pub mod tailpipe;
 pub mod utils;
+pub mod validate;
 #[cfg(feature = "godot")]
 use godot::prelude::*;
 
@@ -0,0 +1,205 @@
+//! ## Listener module ##
+//!
+//! Where the engine is heard from.
+//!
+//! Every perspective balances the stems differently, and may hear them through a wall (the firewall and the windows)
+//! and a resonance of the space it is in. Moving the camera blends between two perspectives.
+//!
+#![warn(clippy::suboptimal_flops, clippy::use_self, clippy::dbg_macro)]
+use crate::error::Error;
+use crate::filter::{Biquad, Shape};
+use crate::gen::{LowPassFilter, Stems};
+use crate::utils::FExt;
+use std::convert::TryFrom;
+use std::f32::consts::FRAC_PI_2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Perspective {
+    /// in front of the car, where the intake is loudest
+    #[default]
+    ExteriorFront,
+    /// behind the car, next to the tailpipe
+    ExteriorRear,
+    /// in the cabin, behind the firewall
+    Interior,
+    /// in the engine bay
+    Onboard,
+}
+
+impl Perspective {
+    pub const ALL: [Self; 4] = [
+        Self::ExteriorFront,
+        Self::ExteriorRear,
+        Self::Interior,
+        Self::Onboard,
+    ];
+
+    /// 0: exterior front, 1: exterior rear, 2: interior, 3: onboard
+    pub fn from_index(index: i32) -> Option<Self> {
+        Self::ALL.get(usize::try_from(index).ok()?).copied()
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// How the engine sounds from one perspective.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct View {
+    /// gain of each stem
+    pub balance: Stems,
+    /// how much of the sound passes through the wall only, 0.0 - 1.0
+    pub muffle: f32,
+    /// the wall between the engine and the listener
+    pub muffle_lp: LowPassFilter,
+    /// resonance of the space around the listener
+    pub resonance: Option<Biquad>,
+    pub resonance_gain: f32,
+}
+
+impl View {
+    fn hear(&mut self, stems: &Stems) -> f32 {
+        let dry = stems.weighted(&self.balance);
+        let muffled = self.muffle_lp.filter(dry);
+        let heard = (muffled - dry).madd(self.muffle, dry);
+        match &mut self.resonance {
+            Some(resonance) => resonance.filter(heard).madd(self.resonance_gain, heard),
+            None => heard,
+        }
+    }
+
+    fn hear_stereo(&mut self, left: &Stems, right: &Stems) -> (f32, f32) {
+        let dry = (left.weighted(&self.balance), right.weighted(&self.balance));
+        let muffled = self.muffle_lp.filter_stereo(dry.0, dry.1);
+        let heard = (
+            (muffled.0 - dry.0).madd(self.muffle, dry.0),
+            (muffled.1 - dry.1).madd(self.muffle, dry.1),
+        );
+        match &mut self.resonance {
+            Some(resonance) => {
+                let resonated = resonance.filter_stereo(heard.0, heard.1);
+                (
+                    resonated.0.madd(self.resonance_gain, heard.0),
+                    resonated.1.madd(self.resonance_gain, heard.1),
+                )
+            }
+            None => heard,
+        }
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Listener {
+    /// one for every `Perspective`, in order
+    pub views: [View; 4],
+    pub from: Perspective,
+    pub to: Perspective,
+    /// 0.0 (`from`) - 1.0 (`to`)
+    pub blend: f32,
+}
+
+impl Listener {
+    pub fn new(samples_per_second: u32) -> Result<Self, Error> {
+        let sps = samples_per_second;
+        let balance = |intake, exhaust, vibration, driveline, induction, cabin, mechanical| Stems {
+            intake,
+            vibration,
+            exhaust,
+            driveline,
+            induction,
+            cabin,
+            mechanical,
+        };
+        Ok(Self {
+            views: [
+                View {
+                    balance: balance(1.0, 0.6, 1.0, 1.0, 1.5, 0.0, 1.0),
+                    muffle_lp: LowPassFilter::new(20000.0, sps)?,
+                    ..Default::default()
+                },
+                View {
+                    balance: balance(0.5, 1.3, 0.7, 0.8, 0.6, 0.0, 0.3),
+                    muffle_lp: LowPassFilter::new(20000.0, sps)?,
+                    ..Default::default()
+                },
+                View {
+                    balance: balance(0.4, 0.5, 0.8, 0.7, 0.4, 1.0, 0.3),
+                    muffle: 0.8,
+                    muffle_lp: LowPassFilter::new(400.0, sps)?,
+                    resonance: Some(Biquad::new(Shape::BandPass, 120.0, 3.0, sps)),
+                    resonance_gain: 0.5,
+                },
+                View {
+                    balance: balance(1.2, 0.4, 1.8, 1.2, 1.8, 0.0, 2.0),
+                    muffle_lp: LowPassFilter::new(20000.0, sps)?,
+                    resonance: Some(Biquad::new(Shape::BandPass, 300.0, 4.0, sps)),
+                    resonance_gain: 0.3,
+                    ..Default::default()
+                },
+            ],
+            from: Perspective::ExteriorFront,
+            to: Perspective::ExteriorFront,
+            blend: 0.0,
+        })
+    }
+
+    /// Heard from `perspective` only.
+    pub fn at(mut self, perspective: Perspective) -> Self {
+        self.from = perspective;
+        self.to = perspective;
+        self
+    }
+
+    pub fn resample(&mut self, samples_per_second: u32) {
+        for view in self.views.iter_mut() {
+            view.muffle_lp.resample(samples_per_second);
+            if let Some(resonance) = &mut view.resonance {
+                resonance.resample(samples_per_second);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for view in self.views.iter_mut() {
+            view.muffle_lp.clear();
+            if let Some(resonance) = &mut view.resonance {
+                resonance.clear();
+            }
+        }
+    }
+
+    /// equal power gains of `from` and `to`
+    fn gains(&self) -> (f32, f32) {
+        if self.from == self.to {
+            return (1.0, 0.0);
+        }
+        let angle = self.blend.clamp(0.0, 1.0) * FRAC_PI_2;
+        (angle.cosf(), angle.sinf())
+    }
+
+    /// takes in the stems at the engine's volumes
+    pub fn hear(&mut self, stems: &Stems) -> f32 {
+        // every view keeps running, so its filters are settled when the camera moves to it
+        let mut heard = [0.0; 4];
+        for (heard, view) in heard.iter_mut().zip(self.views.iter_mut()) {
+            *heard = view.hear(stems);
+        }
+        let (from, to) = self.gains();
+        heard[self.from.index()].madd(from, heard[self.to.index()] * to)
+    }
+
+    /// `hear` for the left and right channel, whose stems differ by the panning of the exhaust
+    pub fn hear_stereo(&mut self, left: &Stems, right: &Stems) -> (f32, f32) {
+        let mut heard = [(0.0, 0.0); 4];
+        for (heard, view) in heard.iter_mut().zip(self.views.iter_mut()) {
+            *heard = view.hear_stereo(left, right);
+        }
+        let (from, to) = self.gains();
+        let (a, b) = (heard[self.from.index()], heard[self.to.index()]);
+        (a.0.madd(from, b.0 * to), a.1.madd(from, b.1 * to))
+    }
+}
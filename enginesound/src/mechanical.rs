@@ -0,0 +1,212 @@
+//! ## Mechanical module ##
+//!
+//! The noise of the moving parts, besides the combustion:
+//! - the valves ticking as they hit their seats, twice per cylinder and cycle
+//! - the injectors clicking, once per cylinder and cycle, or once per face of a rotor
+//! - the timing chain or belt whining at its tooth frequency
+//! - the alternator whining at its pole frequency
+//!
+//! Everything is synchronised to the crankshaft position.
+//!
+#![warn(clippy::suboptimal_flops, clippy::use_self, clippy::dbg_macro)]
+use crate::filter::{Biquad, Shape};
+use crate::gen::{Noise, Rotor, PI2F};
+use crate::utils::FExt;
+
+/// Short bursts of noise ringing at a resonance.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Clicks {
+    pub resonator: Biquad,
+    /// time for a click to fall to 1/e, in seconds
+    pub decay: f32,
+    pub level: f32,
+
+    // running values
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub envelope: f32,
+}
+
+impl Clicks {
+    pub fn new(freq: f32, q: f32, decay: f32, level: f32, samples_per_second: u32) -> Self {
+        Self {
+            resonator: Biquad::new(Shape::BandPass, freq, q, samples_per_second),
+            decay,
+            level,
+            envelope: 0.0,
+        }
+    }
+
+    pub fn trigger(&mut self) {
+        self.envelope = 1.0;
+    }
+
+    fn step(&mut self, noise: f32, samples_per_second: u32) -> f32 {
+        let out = self.resonator.filter(noise * self.envelope) * self.level;
+        self.envelope *= (-1.0 / (self.decay * samples_per_second as f32)).exp();
+        out
+    }
+}
+
+/// A whine at a fixed number of periods per crankshaft revolution.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Whine {
+    /// periods per crankshaft revolution, eg. sprocket teeth times the sprocket's speed
+    pub order: f32,
+    pub level: f32,
+
+    // running values
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub phase: f32,
+}
+
+impl Whine {
+    pub fn new(order: f32, level: f32) -> Self {
+        Self {
+            order,
+            level,
+            phase: 0.0,
+        }
+    }
+
+    fn step(&mut self, rpm: f32, samples_per_second: u32) -> f32 {
+        self.phase = (rpm / 60.0 * self.order / samples_per_second as f32 + self.phase).fract();
+        (self.phase * PI2F).sinf() * self.level
+    }
+}
+
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mechanical {
+    pub valvetrain: Clicks,
+    /// where the valves hit their seats, 0.0 - 1.0 of a cycle after the cylinder's `crank_offset`
+    pub valve_events: Box<[f32]>,
+    pub injectors: Clicks,
+    /// where the injector fires, 0.0 - 1.0 of a cycle after the cylinder's `crank_offset`
+    pub injection: f32,
+    pub timing_chain: Whine,
+    /// how much the chain's whine swells and fades with its slack, 0.0 - 1.0
+    pub chain_slack: f32,
+    pub alternator: Whine,
+
+    // running values
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub noise: Noise,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub last_crankshaft_pos: f32,
+    /// the position of every rotor without the crankshaft fluctuation, see `Events::rotors`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub rotors: Vec<f32>,
+}
+
+/// whether `at` lies between `from` (exclusive) and `to` (inclusive), wrapping around at 1.0
+fn crossed(from: f32, to: f32, at: f32) -> bool {
+    let span = (to - from).rem_euclid(1.0);
+    let distance = (at - from).rem_euclid(1.0);
+    0.0 < distance && distance <= span
+}
+
+impl Mechanical {
+    /// A modern petrol engine with port injection and a timing chain.
+    pub fn new(samples_per_second: u32) -> Self {
+        let sps = samples_per_second;
+        Self {
+            valvetrain: Clicks::new(3500.0, 6.0, 0.002, 1.0, sps),
+            valve_events: vec![0.0, 0.25].into(),
+            injectors: Clicks::new(6000.0, 8.0, 0.001, 0.4, sps),
+            injection: 0.15,
+            // 36 teeth on the cam sprocket, turning at half the crankshaft speed
+            timing_chain: Whine::new(18.0, 0.01),
+            chain_slack: 0.3,
+            // a 2.5:1 pulley and 12 poles with 3 phases
+            alternator: Whine::new(90.0, 0.004),
+            noise: Noise::default(),
+            last_crankshaft_pos: 0.0,
+            rotors: Vec::new(),
+        }
+    }
+
+    /// An old diesel with clattering injectors and gear driven camshafts.
+    pub fn diesel(samples_per_second: u32) -> Self {
+        let sps = samples_per_second;
+        Self {
+            valvetrain: Clicks::new(2500.0, 5.0, 0.003, 1.4, sps),
+            injectors: Clicks::new(4000.0, 4.0, 0.003, 2.0, sps),
+            // injected right before the piston reaches the top
+            injection: 0.48,
+            // 40 teeth on the cam gear
+            timing_chain: Whine::new(20.0, 0.02),
+            chain_slack: 0.1,
+            ..Self::new(sps)
+        }
+    }
+
+    pub fn resample(&mut self, samples_per_second: u32) {
+        self.valvetrain.resonator.resample(samples_per_second);
+        self.injectors.resonator.resample(samples_per_second);
+    }
+
+    pub fn clear(&mut self) {
+        for clicks in [&mut self.valvetrain, &mut self.injectors] {
+            clicks.resonator.clear();
+            clicks.envelope = 0.0;
+        }
+    }
+
+    /// takes in the crankshaft position (0.0 - 1.0 per cycle), the engine speed, the cylinders' `crank_offset`s and the rotors
+    /// returns the mechanical noise
+    pub fn process(
+        &mut self,
+        crankshaft_pos: f32,
+        rpm: f32,
+        crank_offsets: impl Iterator<Item = f32>,
+        rotors: &[Rotor],
+        samples_per_second: u32,
+    ) -> f32 {
+        let (from, to) = (self.last_crankshaft_pos, crankshaft_pos);
+        self.last_crankshaft_pos = crankshaft_pos;
+        for offset in crank_offsets {
+            if self
+                .valve_events
+                .iter()
+                .any(|event| crossed(from, to, (event - offset).rem_euclid(1.0)))
+            {
+                self.valvetrain.trigger();
+            }
+            if crossed(from, to, (self.injection - offset).rem_euclid(1.0)) {
+                self.injectors.trigger();
+            }
+        }
+        if self.rotors.len() != rotors.len() {
+            self.rotors = rotors.iter().map(|rotor| rotor.rotor_pos).collect();
+        }
+        // a crank cycle is two shaft revolutions, a rotor revolution is three, see `Rotor::pop`
+        let mut delta = to - from;
+        delta -= delta.round();
+        for (rotor, pos) in rotors.iter().zip(self.rotors.iter_mut()) {
+            let before = *pos;
+            *pos = delta.madd(2.0 / 3.0, before).rem_euclid(1.0);
+            // the ports are in the housing, so only the injectors click
+            for face in 0..Rotor::FACES {
+                let face = rotor.rotor_offset + face as f32 / Rotor::FACES as f32;
+                if crossed(before, *pos, (self.injection - face).rem_euclid(1.0)) {
+                    self.injectors.trigger();
+                }
+            }
+        }
+
+        let noise = self.noise.step();
+        // the chain tightens and slackens once per revolution
+        let slack = self
+            .chain_slack
+            .madd((crankshaft_pos * 2.0 * PI2F).sinf(), 1.0);
+        let sps = samples_per_second;
+        self.timing_chain.step(rpm, sps).madd(
+            slack,
+            self.valvetrain.step(noise, sps)
+                + self.injectors.step(noise, sps)
+                + self.alternator.step(rpm, sps),
+        )
+    }
+}
@@ -0,0 +1,406 @@
+//! ## Network module ##
+//!
+//! Describes an exhaust as a graph of pipes instead of the fixed `Muffler` layout.
+//!
+//! Every pipe is a `WaveGuide` without reflections of its own.
+//! The ends of the pipes meet at junctions, where the pressure waves scatter depending on the pipes' cross sections,
+//! or end in a termination, which reflects, absorbs or radiates them.
+//! Dual exhausts, X- and H-pipes, resonators and catalytic converters are all built from these.
+//!
+#![warn(clippy::suboptimal_flops, clippy::use_self, clippy::dbg_macro)]
+use crate::error::Error;
+use crate::filter::{Biquad, Shape};
+use crate::gen::WaveGuide;
+use crate::utils::FExt;
+
+/// One end of a pipe.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum End {
+    /// the x0 side of the waveguide
+    Alpha,
+    /// the x1 side of the waveguide
+    Beta,
+}
+
+impl End {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pipe {
+    pub name: String,
+    /// the pipe's delay, its `alpha` and `beta` are ignored
+    pub guide: WaveGuide,
+    /// cross section, in m²
+    pub area: f32,
+    /// fraction of a wave lost while passing through the pipe, eg. in a catalytic converter
+    pub loss: f32,
+}
+
+impl Pipe {
+    /// A pipe `delay` seconds long.
+    pub fn new(name: &str, delay: f32, area: f32, samples_per_second: u32) -> Result<Self, Error> {
+        Ok(Self {
+            name: name.to_owned(),
+            guide: WaveGuide::new(
+                (delay * samples_per_second as f32) as usize,
+                0.0,
+                0.0,
+                samples_per_second,
+            )?,
+            area,
+            loss: 0.0,
+        })
+    }
+}
+
+/// Where pipes meet.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Junction {
+    /// names and ends of the pipes meeting here
+    pub ends: Box<[(String, End)]>,
+    /// indices of `ends` in the network's pipes
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub pipes: Box<[(usize, End)]>,
+}
+
+impl Junction {
+    pub fn new(ends: &[(&str, End)]) -> Self {
+        Self {
+            ends: ends
+                .iter()
+                .map(|&(name, end)| (name.to_owned(), end))
+                .collect(),
+            pipes: Box::new([]),
+        }
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Termination {
+    /// the exhaust collector feeds in here, and gets the arriving wave as back pressure
+    Inlet,
+    /// radiates into the open, reflecting `reflection` of the wave with its phase inverted.
+    /// whatever is not reflected is the output of the network
+    Open { reflection: f32 },
+    /// reflects everything
+    Closed,
+    /// a side branch resonator, which absorbs around its resonance
+    Resonator {
+        resonator: Biquad,
+        /// 0.0 - 1.0
+        strength: f32,
+    },
+}
+
+/// A pipe end that is not connected to other pipes.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Terminal {
+    pub pipe: String,
+    pub end: End,
+    pub termination: Termination,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub index: usize,
+}
+
+impl Terminal {
+    pub fn new(pipe: &str, end: End, termination: Termination) -> Self {
+        Self {
+            pipe: pipe.to_owned(),
+            end,
+            termination,
+            index: 0,
+        }
+    }
+}
+
+/// A pipe name which is not in the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPipe(pub String);
+
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Network {
+    pub pipes: Box<[Pipe]>,
+    pub junctions: Box<[Junction]>,
+    pub terminals: Box<[Terminal]>,
+
+    // running values
+    /// waves arriving at each pipe's alpha and beta end
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub arriving: Box<[[f32; 2]]>,
+    /// waves leaving into each pipe's alpha and beta end
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub leaving: Box<[[f32; 2]]>,
+}
+
+impl Network {
+    pub fn new(
+        pipes: Vec<Pipe>,
+        junctions: Vec<Junction>,
+        terminals: Vec<Terminal>,
+    ) -> Result<Self, UnknownPipe> {
+        let mut network = Self {
+            pipes: pipes.into(),
+            junctions: junctions.into(),
+            terminals: terminals.into(),
+            ..Default::default()
+        };
+        network.resolve()?;
+        Ok(network)
+    }
+
+    /// Looks up the pipes of the junctions and terminals by name. Has to be called after deserializing.
+    pub fn resolve(&mut self) -> Result<(), UnknownPipe> {
+        let pipes = &self.pipes;
+        let find = |name: &str| {
+            pipes
+                .iter()
+                .position(|pipe| pipe.name == name)
+                .ok_or_else(|| UnknownPipe(name.to_owned()))
+        };
+        for junction in self.junctions.iter_mut() {
+            junction.pipes = junction
+                .ends
+                .iter()
+                .map(|(name, end)| Ok((find(name)?, *end)))
+                .collect::<Result<_, _>>()?;
+        }
+        for terminal in self.terminals.iter_mut() {
+            terminal.index = find(&terminal.pipe)?;
+        }
+        self.arriving = vec![[0.0; 2]; self.pipes.len()].into();
+        self.leaving = vec![[0.0; 2]; self.pipes.len()].into();
+        Ok(())
+    }
+
+    /// A dual exhaust: the collector splits into two pipes, each with a catalytic converter,
+    /// joined by an X-pipe, with a side branch resonator on the left side.
+    pub fn dual(samples_per_second: u32) -> Result<Self, Error> {
+        let sps = samples_per_second;
+        let pipe = |name, delay, area| Pipe::new(name, delay, area, sps);
+        let cat = |name| -> Result<Pipe, Error> {
+            Ok(Pipe {
+                loss: 0.15,
+                ..pipe(name, 0.0004166667, 0.012)?
+            })
+        };
+        use End::*;
+        Ok(Self::new(
+            vec![
+                pipe("downpipe", 0.0010416667, 0.0025)?,
+                pipe("left", 0.0008333333, 0.0020)?,
+                pipe("right", 0.0008958333, 0.0020)?,
+                cat("left cat")?,
+                cat("right cat")?,
+                pipe("left mid", 0.0022916667, 0.0020)?,
+                pipe("right mid", 0.0022916667, 0.0020)?,
+                pipe("left tail", 0.003125, 0.0020)?,
+                pipe("right tail", 0.0033333333, 0.0020)?,
+                pipe("left resonator", 0.0002083333, 0.0010)?,
+            ],
+            vec![
+                Junction::new(&[("downpipe", Beta), ("left", Alpha), ("right", Alpha)]),
+                Junction::new(&[("left", Beta), ("left cat", Alpha)]),
+                Junction::new(&[("right", Beta), ("right cat", Alpha)]),
+                // the X-pipe
+                Junction::new(&[
+                    ("left cat", Beta),
+                    ("right cat", Beta),
+                    ("left mid", Alpha),
+                    ("right mid", Alpha),
+                ]),
+                Junction::new(&[
+                    ("left mid", Beta),
+                    ("left tail", Alpha),
+                    ("left resonator", Alpha),
+                ]),
+                Junction::new(&[("right mid", Beta), ("right tail", Alpha)]),
+            ],
+            vec![
+                Terminal::new("downpipe", Alpha, Termination::Inlet),
+                Terminal::new(
+                    "left resonator",
+                    Beta,
+                    Termination::Resonator {
+                        resonator: Biquad::new(Shape::BandPass, 110.0, 2.0, sps),
+                        strength: 0.5,
+                    },
+                ),
+                Terminal::new("left tail", Beta, Termination::Open { reflection: 0.6 }),
+                Terminal::new("right tail", Beta, Termination::Open { reflection: 0.6 }),
+            ],
+        )?)
+    }
+
+    pub fn resample(&mut self, samples_per_second: u32) -> Result<(), Error> {
+        for pipe in self.pipes.iter_mut() {
+            pipe.guide.resample(samples_per_second)?;
+        }
+        for terminal in self.terminals.iter_mut() {
+            if let Termination::Resonator { resonator, .. } = &mut terminal.termination {
+                resonator.resample(samples_per_second);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.pipes
+            .iter_mut()
+            .flat_map(|pipe| [&mut pipe.guide.chamber0, &mut pipe.guide.chamber1])
+            .for_each(|chamber| chamber.samples.data.iter_mut().for_each(|x| *x = 0.0));
+        for terminal in self.terminals.iter_mut() {
+            if let Termination::Resonator { resonator, .. } = &mut terminal.termination {
+                resonator.clear();
+            }
+        }
+    }
+
+    /// takes in the exhaust collector pressure and the wave the tailpipe reflects back, shared by the open ends
+    /// returns `(back pressure, output)`
+    pub fn process(&mut self, exhaust_collector: f32, tailpipe: f32) -> (f32, f32) {
+        for (pipe, arriving) in self.pipes.iter_mut().zip(self.arriving.iter_mut()) {
+            pipe.guide.alpha = 0.0;
+            pipe.guide.beta = 0.0;
+            let ret = pipe.guide.pop();
+            let pass = 1.0 - pipe.loss;
+            *arriving = [ret.0 * pass, ret.1 * pass];
+        }
+        self.leaving.iter_mut().for_each(|x| *x = [0.0; 2]);
+
+        for junction in self.junctions.iter() {
+            // the pressure at the junction, from the waves arriving weighted by their pipe's cross section
+            let (mut pressure, mut area) = (0.0, 0.0);
+            for &(pipe, end) in junction.pipes.iter() {
+                let a = self.pipes[pipe].area;
+                pressure = a.madd(self.arriving[pipe][end.index()], pressure);
+                area += a;
+            }
+            if area <= 0.0 {
+                continue;
+            }
+            let pressure = 2.0 * pressure / area;
+            for &(pipe, end) in junction.pipes.iter() {
+                self.leaving[pipe][end.index()] = pressure - self.arriving[pipe][end.index()];
+            }
+        }
+
+        let inlets = self
+            .terminals
+            .iter()
+            .filter(|terminal| matches!(terminal.termination, Termination::Inlet))
+            .count()
+            .max(1) as f32;
+        let opens = self
+            .terminals
+            .iter()
+            .filter(|terminal| matches!(terminal.termination, Termination::Open { .. }))
+            .count()
+            .max(1) as f32;
+        let (mut back_pressure, mut output) = (0.0, 0.0);
+        for terminal in self.terminals.iter_mut() {
+            let end = terminal.end.index();
+            let arriving = self.arriving[terminal.index][end];
+            self.leaving[terminal.index][end] = match &mut terminal.termination {
+                Termination::Inlet => {
+                    back_pressure += arriving;
+                    exhaust_collector / inlets
+                }
+                Termination::Open { reflection } => {
+                    output = arriving.madd(1.0 - reflection.abs(), output);
+                    (-*reflection).madd(arriving, tailpipe / opens)
+                }
+                Termination::Closed => arriving,
+                Termination::Resonator {
+                    resonator,
+                    strength,
+                } => resonator.filter(arriving).madd(-2.0 * *strength, arriving),
+            };
+        }
+
+        for (pipe, leaving) in self.pipes.iter_mut().zip(self.leaving.iter()) {
+            pipe.guide.push(leaving[0], leaving[1]);
+        }
+
+        (back_pressure, output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use End::*;
+
+    fn pipes() -> Vec<Pipe> {
+        ["a", "b", "c"]
+            .iter()
+            .map(|name| Pipe::new(name, 0.001, 0.002, 48000).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn resolves_names_to_indices() {
+        let network = Network::new(
+            pipes(),
+            vec![Junction::new(&[("a", Beta), ("c", Alpha), ("b", Alpha)])],
+            vec![
+                Terminal::new("a", Alpha, Termination::Inlet),
+                Terminal::new("b", Beta, Termination::Closed),
+                Terminal::new("c", Beta, Termination::Open { reflection: 0.5 }),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            &*network.junctions[0].pipes,
+            &[(0, Beta), (2, Alpha), (1, Alpha)]
+        );
+        let indices: Vec<usize> = network.terminals.iter().map(|t| t.index).collect();
+        assert_eq!(indices, [0, 1, 2]);
+        assert_eq!(network.arriving.len(), 3);
+        assert_eq!(network.leaving.len(), 3);
+    }
+
+    #[test]
+    fn unknown_pipes_are_reported() {
+        let junction = Network::new(
+            pipes(),
+            vec![Junction::new(&[("a", Beta), ("d", Alpha)])],
+            Vec::new(),
+        );
+        assert_eq!(junction.err(), Some(UnknownPipe("d".to_owned())));
+
+        let terminal = Network::new(
+            pipes(),
+            Vec::new(),
+            vec![Terminal::new("e", Alpha, Termination::Inlet)],
+        );
+        assert_eq!(terminal.err(), Some(UnknownPipe("e".to_owned())));
+    }
+
+    #[test]
+    fn resolves_again_after_deserializing() {
+        let mut network = Network::dual(48000).unwrap();
+        let pipes: Vec<_> = network
+            .junctions
+            .iter()
+            .map(|junction| junction.pipes.clone())
+            .collect();
+        for junction in network.junctions.iter_mut() {
+            junction.pipes = Box::new([]);
+        }
+        network.arriving = Box::new([]);
+        network.resolve().unwrap();
+        for (junction, pipes) in network.junctions.iter().zip(pipes) {
+            assert_eq!(junction.pipes, pipes);
+        }
+        assert_eq!(network.arriving.len(), network.pipes.len());
+    }
+}
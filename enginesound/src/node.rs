@@ -1,60 +1,763 @@
-use std::cell::OnceCell;
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError, Weak};
 
-use crate::gen::{Engine, Generator, LowPassFilter};
-use godot::engine::{AudioStreamGenerator, AudioStreamGeneratorPlayback, IAudioStreamGenerator};
+use crate::body::Body;
+use crate::config::EngineSoundConfig;
+use crate::driveline::Transmission;
+use crate::dynamics::Dynamics;
+use crate::effects::{Compressor, Effect, Limiter, Reverb, MAX_ROOM_SIZE};
+use crate::electric::{ElectricGenerator, Motor};
+use crate::error::{check_positive, Error};
+use crate::events::{Event, Events, Timed};
+use crate::filter::DcBlocker;
+use crate::gen::{Engine, Generator, Ignition};
+use crate::intake::Intake;
+use crate::listener::{Listener, Perspective};
+use crate::mechanical::Mechanical;
+use crate::rumble::Rumble;
+use crate::tailpipe::Tailpipe;
+use crate::utils::FExt;
+use godot::engine::native::AudioFrame;
+use godot::engine::{
+    AudioServer, AudioStream, AudioStreamGenerator, AudioStreamGeneratorPlayback,
+    AudioStreamPlayback, IAudioStream, IAudioStreamGenerator, IAudioStreamPlayback,
+};
 use godot::prelude::*;
 
 type Stream = Gd<AudioStreamGeneratorPlayback>;
 
+/// A playback's generator, rendered on the audio thread and controlled from the main thread.
+/// None while the properties don't make an engine, the playback stays silent then.
+type Shared = Arc<Mutex<Option<Generator>>>;
+
+fn lock(generator: &Shared) -> MutexGuard<Option<Generator>> {
+    // a panic while rendering leaves the generator usable
+    generator.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// `lock` without waiting, for the audio thread. `None` while the game thread holds the generator.
+fn try_lock(generator: &Shared) -> Option<MutexGuard<Option<Generator>>> {
+    match generator.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
 /// Procedural engine sound generation
 #[derive(GodotClass)]
-#[class(base=AudioStreamGenerator)]
+#[class(tool, base=AudioStream)]
 pub struct EngineStream {
-    /// if this was set in init() the mix rate would be wrong
-    generator: OnceCell<Generator>,
-    stream: OnceCell<Stream>,
+    /// every playback runs its own generator, the properties are handed to all of them every frame
+    playbacks: RefCell<Vec<Weak<Mutex<Option<Generator>>>>>,
     /// The rotations per minute of the engine.
-    #[var]
+    /// While [member simulate] is on, this is the simulated speed, and setting it has no effect.
+    #[export(range = (0.0, 8000.0, or_greater))]
     engine_rpm: f32,
-    base: Base<AudioStreamGenerator>,
+    /// Simulates the engine speed from [member throttle] and the driveline, instead of using [member engine_rpm].
+    #[export]
+    simulate: bool,
+    /// How far the throttle is opened, from 0 to 1. Also drives the engine speed while [member simulate] is on.
+    #[export(range = (0.0, 1.0))]
+    throttle: f32,
+    /// The volume of the induction roar coming out of the air box.
+    #[export(range = (0.0, 2.0, or_greater))]
+    induction_volume: f32,
+    /// Gives every cylinder its own throttle and trumpet, instead of a shared air box.
+    #[export]
+    individual_throttle_bodies: bool,
+    /// The rotations per minute of the driven wheels.
+    #[export(range = (0.0, 3000.0, or_greater))]
+    wheel_rpm: f32,
+    /// The selected gear. 0 is neutral, -1 is reverse, and gears past the top gear are the top gear.
+    #[export(range = (-1.0, 6.0, or_greater))]
+    gear: i32,
+    /// How far the clutch is engaged, from 0 (pedal down) to 1 (pedal up).
+    #[export(range = (0.0, 1.0))]
+    clutch: f32,
+    /// The diameter of the tailpipe's open end, in meters. Wider pipes sound darker.
+    #[var(get, set = set_tailpipe_diameter)]
+    tailpipe_diameter: f32,
+    /// Where the engine is heard from: 0 exterior front, 1 exterior rear, 2 interior, 3 onboard (engine bay).
+    #[var(get, set = set_perspective)]
+    #[export(enum = (ExteriorFront, ExteriorRear, Interior, Onboard))]
+    perspective: i32,
+    /// The perspective the camera is moving to, see [member perspective].
+    #[var(get, set = set_next_perspective)]
+    #[export(enum = (ExteriorFront, ExteriorRear, Interior, Onboard))]
+    next_perspective: i32,
+    /// How far the camera has moved from [member perspective] to [member next_perspective], from 0 to 1.
+    #[export(range = (0.0, 1.0))]
+    perspective_blend: f32,
+    /// Makes the engine a turbo diesel. Changing it restarts the engine.
+    #[export]
+    diesel: bool,
+    /// The cylinders, the muffler and the filters of the engine. Without one, the default engine plays.
+    /// Editing it restarts the engine.
+    #[var(get, set = set_config)]
+    #[export]
+    config: Option<Gd<EngineSoundConfig>>,
+    /// Boost or cut of the lows (below 200 Hz), in dB.
+    #[export(range = (-24.0, 24.0))]
+    eq_low: f32,
+    /// Boost or cut of the mids (around 1 kHz), in dB.
+    #[export(range = (-24.0, 24.0))]
+    eq_mid: f32,
+    /// Boost or cut of the highs (above 5 kHz), in dB.
+    #[export(range = (-24.0, 24.0))]
+    eq_high: f32,
+    /// How hard the sound is driven into the soft clipper. 1 is clean.
+    #[export(range = (1.0, 10.0, or_greater))]
+    saturation_drive: f32,
+    /// The compressor reduces the gain above this level, in dB. 0 turns it off.
+    #[export(range = (-60.0, 0.0))]
+    compressor_threshold: f32,
+    /// How strongly the compressor reduces the gain above [member compressor_threshold].
+    #[export(range = (1.0, 20.0, or_greater))]
+    compressor_ratio: f32,
+    /// How much reverb is added, from 0 to 1. Use it for tunnels and garages.
+    #[export(range = (0.0, 1.0))]
+    reverb_mix: f32,
+    /// The size of the reverberating room, from 0 to 0.98. Bigger rooms ring longer.
+    #[export(range = (0.0, 0.98))]
+    reverb_room_size: f32,
+    /// The output never goes above this level, from 0 to 1. Louder peaks are turned down by the limiter.
+    #[export(range = (0.0, 1.0))]
+    limiter_ceiling: f32,
+    /// The time in seconds the limiter takes to turn the sound back up after a peak.
+    #[export(range = (0.0, 1.0, or_greater))]
+    limiter_release: f32,
+    /// The overall volume, before the effects.
+    #[export(range = (0.0, 1.0, or_greater))]
+    volume: f32,
+    /// The volume of the pressure waves in the intake.
+    #[export(range = (0.0, 2.0, or_greater))]
+    intake_volume: f32,
+    /// The volume of the exhaust.
+    #[export(range = (0.0, 2.0, or_greater))]
+    exhaust_volume: f32,
+    /// The volume of the engine block's vibrations.
+    #[export(range = (0.0, 0.5, or_greater))]
+    engine_vibrations_volume: f32,
+    /// Shifts the intake valve timing, in fractions of a cycle.
+    #[export(range = (-0.5, 0.5))]
+    intake_valve_shift: f32,
+    /// Shifts the exhaust valve timing, in fractions of a cycle.
+    #[export(range = (-0.5, 0.5))]
+    exhaust_valve_shift: f32,
+    /// How unevenly the crankshaft turns. Higher values sound rougher.
+    #[export(range = (0.0, 2.0, or_greater))]
+    crankshaft_fluctuation: f32,
+    /// The amount of noise from the air rushing into the intake.
+    #[export(range = (0.0, 5.0, or_greater))]
+    intake_noise_factor: f32,
+    base: Base<AudioStream>,
 }
 
 #[godot_api]
-impl IAudioStreamGenerator for EngineStream {
-    fn init(base: Base<AudioStreamGenerator>) -> Self {
+impl IAudioStream for EngineStream {
+    fn init(base: Base<AudioStream>) -> Self {
         Self {
-            generator: OnceCell::new(),
+            playbacks: RefCell::default(),
             base,
-            stream: OnceCell::new(),
             engine_rpm: 883.0,
+            simulate: false,
+            throttle: 0.0,
+            induction_volume: 0.3,
+            individual_throttle_bodies: false,
+            wheel_rpm: 0.0,
+            gear: 0,
+            clutch: 1.0,
+            tailpipe_diameter: 0.06,
+            perspective: 0,
+            next_perspective: 0,
+            perspective_blend: 0.0,
+            diesel: false,
+            config: None,
+            eq_low: 0.0,
+            eq_mid: 0.0,
+            eq_high: 0.0,
+            saturation_drive: 1.0,
+            compressor_threshold: 0.0,
+            compressor_ratio: 4.0,
+            reverb_mix: 0.0,
+            reverb_room_size: 0.8,
+            limiter_ceiling: 0.98,
+            limiter_release: 0.1,
+            volume: 0.1,
+            intake_volume: 0.32493597,
+            exhaust_volume: 0.63871837,
+            engine_vibrations_volume: 0.036345694,
+            intake_valve_shift: -0.041683555,
+            exhaust_valve_shift: -0.0046506226,
+            crankshaft_fluctuation: 0.4000154,
+            intake_noise_factor: 1.3716942,
         }
     }
+
+    fn instantiate_playback(&self) -> Option<Gd<AudioStreamPlayback>> {
+        let generator = Arc::new(Mutex::new(self.fresh()));
+        self.playbacks.borrow_mut().push(Arc::downgrade(&generator));
+        self.follow_frames();
+        Some(
+            Gd::from_init_fn(|base| EnginePlayback {
+                generator,
+                playing: false,
+                base,
+            })
+            .upcast(),
+        )
+    }
+
+    fn get_stream_name(&self) -> GString {
+        "EngineStream".into()
+    }
+
+    fn get_length(&self) -> f64 {
+        // never ends
+        0.0
+    }
 }
 
 #[godot_api]
 impl EngineStream {
+    /// Builds the generator at the mix rate, with the current properties.
+    fn build(&self) -> Result<Generator, Error> {
+        let sps = AudioServer::singleton().get_mix_rate() as u32;
+        let engine = match &self.config {
+            Some(config) => config.bind().engine(sps)?,
+            None => Engine::new(sps)?,
+        };
+        let engine = if self.diesel {
+            engine.into_diesel(sps)?
+        } else {
+            Engine {
+                intake: Some(Intake::new(
+                    engine.cylinders.len() + engine.rotors.len(),
+                    sps,
+                )?),
+                mechanical: Some(Mechanical::new(sps)),
+                ..engine
+            }
+        };
+        let engine = Engine {
+            tailpipe: Some(Tailpipe::new(self.tailpipe_diameter, sps)?),
+            body: Some(Body::new(sps)?),
+            ..engine
+        };
+        let mut gen = Generator::new(sps, engine, DcBlocker::new(0.5, sps).into())?;
+        gen.transmission = Some(Transmission::default());
+        gen.listener = Some(Listener::new(sps)?);
+        gen.effects = vec![
+            Effect::eq(0.0, 0.0, 0.0, sps),
+            Effect::Saturation {
+                drive: 1.0,
+                mix: 1.0,
+            },
+            Effect::Compressor(Compressor::new(0.0, 4.0, sps)),
+            Effect::Reverb(Reverb {
+                mix: 0.0,
+                ..Reverb::new(sps)
+            }),
+        ];
+        gen.limiter = Some(Limiter::new(0.98, sps));
+        gen.rumble = Some(Rumble::default());
+        Ok(gen)
+    }
+
+    /// A generator built and set up with the current properties, if they make an engine.
+    fn fresh(&self) -> Option<Generator> {
+        match self.build() {
+            Ok(mut gen) => {
+                self.apply(&mut gen);
+                Some(gen)
+            }
+            Err(error) => {
+                godot_error!("can't build the engine: {error}");
+                None
+            }
+        }
+    }
+
+    /// the generators of the playbacks that are still alive
+    fn generators(&self) -> Vec<Shared> {
+        let mut playbacks = self.playbacks.borrow_mut();
+        playbacks.retain(|playback| playback.strong_count() > 0);
+        playbacks.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    /// Calls `sync` every frame.
+    fn follow_frames(&self) {
+        let Some(mut main_loop) = godot::engine::Engine::singleton().get_main_loop() else {
+            return godot_error!("no main loop to follow");
+        };
+        let sync = Callable::from_object_method(&self.to_gd(), "sync");
+        if !main_loop.is_connected("process_frame".into(), sync.clone()) {
+            main_loop.connect("process_frame".into(), sync);
+        }
+    }
+
+    /// Hands the properties to the generators and emits the events of the engine.
+    /// Called every frame while the engine plays.
+    #[func]
+    fn sync(&mut self) {
+        let generators = self.generators();
+        let mut events = Vec::new();
+        for (i, generator) in generators.iter().enumerate() {
+            let diesel = lock(generator)
+                .as_ref()
+                .map(|gen| matches!(gen.engine.ignition, Ignition::Compression { .. }));
+            if diesel.is_some_and(|diesel| diesel != self.diesel) {
+                let gen = self.fresh();
+                *lock(generator) = gen;
+            }
+            if let Some(gen) = lock(generator).as_mut() {
+                self.apply(gen);
+                // only the first player's events are emitted, the others would repeat them
+                if i == 0 {
+                    events = gen.events.get_or_insert_with(Events::default).take();
+                } else {
+                    gen.events = None;
+                }
+            }
+        }
+        for event in events {
+            self.emit(event);
+        }
+        if self.simulate {
+            // the first player's engine speaks for all of them
+            if let Some(rpm) = generators
+                .first()
+                .and_then(|generator| lock(generator).as_ref().map(|gen| gen.engine.rpm))
+            {
+                self.engine_rpm = rpm;
+            }
+        }
+    }
+
+    /// Emits the signal of `event` at the end of the frame, so the handlers can use the stream.
+    fn emit(&mut self, Timed { event, time }: Timed) {
+        let (signal, cylinder) = match event {
+            Event::Ignition(cylinder) => ("cylinder_fired", Some(cylinder)),
+            Event::RevLimiter => ("rev_limiter_hit", None),
+            Event::Backfire(cylinder) => ("backfired", Some(cylinder)),
+            Event::Started => ("started", None),
+            Event::Stalled => ("stalled", None),
+        };
+        let mut args = vec![StringName::from(signal).to_variant()];
+        args.extend(cylinder.map(|cylinder| (cylinder as i64).to_variant()));
+        args.push(time.to_variant());
+        self.base_mut().call_deferred("emit_signal".into(), &args);
+    }
+
+    /// Emitted when a cylinder fires, with its index and the time in seconds the player had played.
+    /// The rotors of a rotary engine are counted after the cylinders, and fire once per face.
+    #[signal]
+    fn cylinder_fired(cylinder: i64, time: f64);
+
+    /// Emitted when the rev limiter starts cutting fuel, with the time in seconds the player had played.
+    #[signal]
+    fn rev_limiter_hit(time: f64);
+
+    /// Emitted when unburnt fuel goes off in the exhaust of a cylinder, after the rev limiter or when the throttle snaps shut at high rpm.
+    /// Comes with the cylinder's index and the time in seconds the player had played.
+    #[signal]
+    fn backfired(cylinder: i64, time: f64);
+
+    /// Emitted when the starter gets the engine running, with the time in seconds the player had played.
+    #[signal]
+    fn started(time: f64);
+
+    /// Emitted when the engine stops, stalled or switched off, with the time in seconds the player had played.
+    #[signal]
+    fn stalled(time: f64);
+
+    fn apply(&self, gen: &mut Generator) {
+        match (self.simulate, &mut gen.dynamics) {
+            (true, Some(dynamics)) => dynamics.throttle = self.throttle,
+            (true, None) => {
+                gen.dynamics = Some(Dynamics {
+                    rpm: self.engine_rpm,
+                    throttle: self.throttle,
+                    ..if self.diesel {
+                        Dynamics::diesel()
+                    } else {
+                        Dynamics::default()
+                    }
+                })
+            }
+            (false, _) => {
+                gen.dynamics = None;
+                gen.engine.rpm = self.engine_rpm;
+            }
+        }
+        gen.volume = self.volume;
+        gen.engine.intake_volume = self.intake_volume;
+        gen.engine.exhaust_volume = self.exhaust_volume;
+        gen.engine.engine_vibrations_volume = self.engine_vibrations_volume;
+        gen.engine.intake_valve_shift = self.intake_valve_shift;
+        gen.engine.exhaust_valve_shift = self.exhaust_valve_shift;
+        gen.engine.crankshaft_fluctuation = self.crankshaft_fluctuation;
+        gen.engine.intake_noise_factor = self.intake_noise_factor;
+        if let Some(intake) = &mut gen.engine.intake {
+            intake.throttle = self.throttle;
+            intake.volume = self.induction_volume;
+            intake.individual_throttle_bodies = self.individual_throttle_bodies;
+        }
+        if let Some(tailpipe) = &mut gen.engine.tailpipe {
+            if tailpipe.diameter != self.tailpipe_diameter {
+                if let Err(error) =
+                    tailpipe.set_diameter(self.tailpipe_diameter, gen.samples_per_second)
+                {
+                    godot_error!("can't set the tailpipe diameter: {error}");
+                }
+            }
+        }
+        if let Some(listener) = &mut gen.listener {
+            // the setters only let known perspectives through
+            if let (Some(from), Some(to)) = (
+                Perspective::from_index(self.perspective),
+                Perspective::from_index(self.next_perspective),
+            ) {
+                listener.from = from;
+                listener.to = to;
+            }
+            listener.blend = self.perspective_blend;
+        }
+        for effect in gen.effects.iter_mut() {
+            match effect {
+                Effect::Eq(bands) => {
+                    for (band, gain) in
+                        bands
+                            .iter_mut()
+                            .zip([self.eq_low, self.eq_mid, self.eq_high])
+                    {
+                        let shape = band.shape.with_gain(gain);
+                        if shape != band.shape {
+                            band.retune(shape, band.freq, band.q, gen.samples_per_second);
+                        }
+                    }
+                }
+                Effect::Saturation { drive, .. } => *drive = self.saturation_drive,
+                Effect::Compressor(compressor) => {
+                    compressor.threshold = self.compressor_threshold;
+                    compressor.ratio = self.compressor_ratio;
+                }
+                Effect::Reverb(reverb) => {
+                    reverb.mix = self.reverb_mix.clamp_nan(0.0, 1.0);
+                    reverb.room_size = self.reverb_room_size.clamp_nan(0.0, MAX_ROOM_SIZE);
+                }
+            }
+        }
+        if let Some(limiter) = &mut gen.limiter {
+            limiter.ceiling = self.limiter_ceiling.clamp(0.0, 1.0);
+            limiter.release = self.limiter_release.max(0.0);
+        }
+        if let Some(transmission) = &mut gen.transmission {
+            transmission.wheel_rpm = self.wheel_rpm;
+            transmission.clutch = self.clutch;
+            transmission.shift(self.gear);
+        }
+    }
+
+    /// Returns the engine speed after coupling it to the wheels.
+    #[func]
+    fn get_rpm(&self) -> f32 {
+        self.generators()
+            .first()
+            .and_then(|generator| lock(generator).as_ref().map(Generator::rpm))
+            .unwrap_or(self.engine_rpm)
+    }
+
+    /// Returns how far the limiter currently turns the sound down, in dB.
+    #[func]
+    fn get_gain_reduction(&self) -> f32 {
+        self.generators()
+            .first()
+            .and_then(|generator| {
+                lock(generator)
+                    .as_ref()
+                    .and_then(|gen| gen.limiter.as_ref().map(Limiter::reduction))
+            })
+            .unwrap_or(0.0)
+    }
+
+    /// Returns the state of every cylinder, as dictionaries with
+    /// [code]phase[/code] (where it is in its cycle, from 0 to 1, firing at 0.5),
+    /// [code]intake_valve[/code] and [code]exhaust_valve[/code] (how far they are opened, from 0 to 1),
+    /// [code]ignition[/code] (the combustion pressure, 0 while not firing)
+    /// and [code]level[/code] (the recent peak of its exhaust).
+    /// The rotors of a rotary engine follow the cylinders, with their ports as valves.
+    /// Empty while the engine doesn't play.
+    #[func]
+    fn get_cylinder_states(&self) -> Array<Dictionary> {
+        let mut states = Array::new();
+        if let Some(generator) = self.generators().first() {
+            if let Some(gen) = lock(generator).as_ref() {
+                for state in gen.engine.cylinder_states() {
+                    states.push(dict! {
+                        "phase": state.phase,
+                        "intake_valve": state.intake_valve,
+                        "exhaust_valve": state.exhaust_valve,
+                        "ignition": state.ignition,
+                        "level": state.level,
+                    });
+                }
+            }
+        }
+        states
+    }
+
+    /// Returns the pressures in the collectors, as a dictionary with [code]intake[/code], [code]exhaust[/code]
+    /// and [code]banks[/code] (the exhaust collector of every bank).
+    /// Empty while the engine doesn't play.
+    #[func]
+    fn get_collector_pressures(&self) -> Dictionary {
+        self.generators()
+            .first()
+            .and_then(|generator| {
+                lock(generator).as_ref().map(|gen| {
+                    let collectors = gen.engine.collectors();
+                    dict! {
+                        "intake": collectors.intake,
+                        "exhaust": collectors.exhaust,
+                        "banks": PackedFloat32Array::from(&collectors.banks[..]),
+                    }
+                })
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the strength of a gamepad's motors, from 0 to 1: [code]x[/code] for the low frequency (strong) motor,
+    /// following the engine vibrations, and [code]y[/code] for the high frequency (weak) motor, following the ignitions.
+    /// Zero while the engine doesn't play.
+    #[func]
+    fn get_rumble(&self) -> Vector2 {
+        self.generators()
+            .first()
+            .and_then(|generator| {
+                lock(generator)
+                    .as_ref()
+                    .and_then(|gen| gen.rumble.as_ref())
+                    .map(|rumble| {
+                        let (low, high) = rumble.motors();
+                        Vector2::new(low, high)
+                    })
+            })
+            .unwrap_or(Vector2::ZERO)
+    }
+
+    /// Starts the engine, if it is simulated and not running.
+    #[func]
+    fn start(&mut self) {
+        for generator in self.generators() {
+            if let Some(dynamics) = lock(&generator)
+                .as_mut()
+                .and_then(|gen| gen.dynamics.as_mut())
+            {
+                dynamics.start();
+            }
+        }
+    }
+
+    /// Stops the engine, if it is simulated.
+    #[func]
+    fn stop(&mut self) {
+        for generator in self.generators() {
+            if let Some(dynamics) = lock(&generator)
+                .as_mut()
+                .and_then(|gen| gen.dynamics.as_mut())
+            {
+                dynamics.stop();
+            }
+        }
+    }
+
+    /// Returns true while the simulated engine is running.
+    #[func]
+    fn is_running(&self) -> bool {
+        self.generators()
+            .first()
+            .and_then(|generator| {
+                lock(generator)
+                    .as_ref()
+                    .and_then(|gen| gen.dynamics.as_ref().map(|dynamics| dynamics.running))
+            })
+            .unwrap_or(true)
+    }
+
+    #[func]
+    fn set_perspective(&mut self, perspective: i32) {
+        if Perspective::from_index(perspective).is_none() {
+            godot_error!("unknown perspective {perspective}, expected 0 - 3");
+            return;
+        }
+        self.perspective = perspective;
+    }
+
+    #[func]
+    fn set_next_perspective(&mut self, perspective: i32) {
+        if Perspective::from_index(perspective).is_none() {
+            godot_error!("unknown perspective {perspective}, expected 0 - 3");
+            return;
+        }
+        self.next_perspective = perspective;
+    }
+
+    #[func]
+    fn set_tailpipe_diameter(&mut self, diameter: f32) {
+        if let Err(error) = check_positive("tailpipe_diameter", diameter) {
+            godot_error!("can't set the tailpipe diameter: {error}");
+            return;
+        }
+        self.tailpipe_diameter = diameter;
+    }
+
+    #[func]
+    fn set_config(&mut self, config: Option<Gd<EngineSoundConfig>>) {
+        let rebuild = Callable::from_object_method(&self.to_gd(), "rebuild");
+        if let Some(old) = &self.config {
+            let mut old = old.clone().upcast::<Object>();
+            if old.is_connected("changed".into(), rebuild.clone()) {
+                old.disconnect("changed".into(), rebuild.clone());
+            }
+        }
+        if let Some(config) = &config {
+            config.bind().watch();
+            config
+                .clone()
+                .upcast::<Object>()
+                .connect("changed".into(), rebuild);
+        }
+        self.config = config;
+        self.rebuild();
+    }
+
+    /// Restarts the engine with the current [member config].
+    #[func]
+    fn rebuild(&mut self) {
+        for generator in self.generators() {
+            // built before locking, the audio thread keeps playing the old engine meanwhile
+            let gen = self.fresh();
+            *lock(&generator) = gen;
+        }
+    }
+}
+
+/// Plays an [EngineStream] with its own engine. Made by the [AudioStreamPlayer], renders the engine on the audio thread.
+#[derive(GodotClass)]
+#[class(tool, base=AudioStreamPlayback)]
+pub struct EnginePlayback {
+    generator: Shared,
+    playing: bool,
+    base: Base<AudioStreamPlayback>,
+}
+
+#[godot_api]
+impl IAudioStreamPlayback for EnginePlayback {
+    fn start(&mut self, _from_pos: f64) {
+        // a restarted player doesn't pick up the echoes of the last time it played
+        if let Some(gen) = lock(&self.generator).as_mut() {
+            gen.reset();
+        }
+        self.playing = true;
+    }
+
+    fn stop(&mut self) {
+        self.playing = false;
+    }
+
+    fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    unsafe fn mix(&mut self, buffer: *mut AudioFrame, _rate_scale: f32, frames: i32) -> i32 {
+        // SAFETY: the audio server hands over a buffer of `frames` frames
+        let buffer = std::slice::from_raw_parts_mut(buffer, frames as usize);
+        // the audio thread never waits for the game thread, a block it can't get the generator for is silent
+        let mut generator = try_lock(&self.generator);
+        match generator.as_deref_mut().and_then(Option::as_mut) {
+            Some(gen) => {
+                for frame in buffer.iter_mut() {
+                    let (left, right) = gen.stereo_frame();
+                    *frame = AudioFrame { left, right };
+                }
+            }
+            None => {
+                for frame in buffer.iter_mut() {
+                    *frame = AudioFrame {
+                        left: 0.0,
+                        right: 0.0,
+                    };
+                }
+            }
+        }
+        frames
+    }
+}
+
+/// Procedural electric motor sound generation
+#[derive(GodotClass)]
+#[class(base=AudioStreamGenerator)]
+pub struct ElectricStream {
+    /// if this was set in init() the mix rate would be wrong
+    generator: Option<ElectricGenerator>,
+    stream: Option<Stream>,
+    /// The rotations per minute of the motor.
+    #[var]
+    motor_rpm: f32,
+    /// How hard the motor is being driven, from 0 to 1.
+    #[var]
+    motor_load: f32,
+    base: Base<AudioStreamGenerator>,
+}
+
+#[godot_api]
+impl IAudioStreamGenerator for ElectricStream {
+    fn init(base: Base<AudioStreamGenerator>) -> Self {
+        Self {
+            generator: None,
+            base,
+            stream: None,
+            motor_rpm: 0.0,
+            motor_load: 0.5,
+        }
+    }
+}
+
+#[godot_api]
+impl ElectricStream {
     /// Fills the [AudioStreamGeneratorPlayback]'s buffer.
     #[func]
     fn update(&mut self) {
-        let b = &self.to_gd();
-        let gen = self.generator.get_mut_or_init(|| {
-            let sps = b.get_mix_rate() as u32;
-            if sps == 0 {
-                godot_error!("0 samples?");
-                unreachable!();
-            }
-            Generator::new(sps, Engine::new(sps), LowPassFilter::new(0.5, sps))
-        });
-        let Some(stream) = self.stream.get_mut() else {
+        if self.generator.is_none() {
+            let sps = self.to_gd().get_mix_rate() as u32;
+            match Motor::new(sps).and_then(|motor| {
+                ElectricGenerator::new(sps, motor, DcBlocker::new(0.5, sps).into())
+            }) {
+                Ok(gen) => self.generator = Some(gen),
+                Err(error) => return godot_error!("can't build the motor: {error}"),
+            }
+        }
+        let Some(gen) = &mut self.generator else {
+            return;
+        };
+        let Some(stream) = &mut self.stream else {
             return godot_error!("No stream! call `set_stream` first.");
         };
-        gen.engine.rpm = self.engine_rpm;
+        gen.motor.rpm = self.motor_rpm;
+        gen.motor.load = self.motor_load;
         gen.generate(stream);
     }
 
-    /// Sets the [AudioStreamGeneratorPlayback] for this engine.
+    /// Sets the [AudioStreamGeneratorPlayback] for this motor, replacing the last one.
+    /// Call it again after restarting the player, or when moving the stream to another player.
     #[func]
     fn set_stream(&mut self, stream: Stream) {
-        self.stream.get_or_init(|| stream);
+        self.stream = Some(stream);
+        // the new playback starts from silence
+        if let Some(gen) = &mut self.generator {
+            gen.reset();
+        }
     }
 }
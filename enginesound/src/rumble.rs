@@ -0,0 +1,73 @@
+//! ## Rumble module ##
+//!
+//! Follows the engine for the two motors of a gamepad:
+//! - the low frequency (strong) motor shakes with the engine vibrations
+//! - the high frequency (weak) motor buzzes with the ignitions
+//!
+//! Both are envelopes in 0.0 - 1.0, which change slowly enough to be read once per frame.
+//!
+#![warn(clippy::suboptimal_flops, clippy::use_self, clippy::dbg_macro)]
+use crate::utils::FExt;
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rumble {
+    /// in s
+    pub attack: f32,
+    /// in s
+    pub release: f32,
+    /// scales the engine vibrations into the low motor
+    pub low_gain: f32,
+    /// scales the ignitions into the high motor
+    pub high_gain: f32,
+
+    // running values
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub low: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub high: f32,
+}
+
+impl Default for Rumble {
+    fn default() -> Self {
+        Self {
+            attack: 0.05,
+            release: 0.15,
+            low_gain: 0.5,
+            high_gain: 2.0,
+            low: 0.0,
+            high: 0.0,
+        }
+    }
+}
+
+impl Rumble {
+    /// the strength of the (low, high) motors, in 0.0 - 1.0
+    pub fn motors(&self) -> (f32, f32) {
+        (
+            (self.low * self.low_gain).min(1.0),
+            (self.high * self.high_gain).min(1.0),
+        )
+    }
+
+    pub fn clear(&mut self) {
+        self.low = 0.0;
+        self.high = 0.0;
+    }
+
+    /// Follows one sample of the filtered engine vibrations and the ignition pressure.
+    pub fn process(&mut self, vibration: f32, ignition: f32, samples_per_second: u32) {
+        self.low = self.follow(self.low, vibration.abs(), samples_per_second);
+        self.high = self.follow(self.high, ignition.abs(), samples_per_second);
+    }
+
+    fn follow(&self, envelope: f32, level: f32, samples_per_second: u32) -> f32 {
+        let time = if level > envelope {
+            self.attack
+        } else {
+            self.release
+        };
+        let follow = 1.0 - (-1.0 / (time * samples_per_second as f32)).exp();
+        (level - envelope).madd(follow, envelope)
+    }
+}
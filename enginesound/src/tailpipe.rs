@@ -0,0 +1,162 @@
+//! ## Tailpipe module ##
+//!
+//! The end of the exhaust, where the sound leaves the pipe.
+//!
+//! An open pipe end reflects the low frequencies back into the pipe and radiates the high ones.
+//! The reflection travels back through the tip, if there is one, and into the muffler or exhaust network.
+//! The crossover lies where the wavelength matches the pipe's circumference, so wider pipes (or tips) sound darker.
+//!
+#![warn(clippy::suboptimal_flops, clippy::use_self, clippy::dbg_macro)]
+use crate::error::{check_positive, Error};
+use crate::filter::Biquad;
+use crate::gen::{LowPassFilter, WaveGuide, PI2F};
+use crate::utils::FExt;
+
+const SPEED_OF_SOUND: f32 = 343.0;
+
+/// A tip on the end of the tailpipe.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tip {
+    /// waveguide from the tailpipe (alpha) to the open end (beta), its `alpha` and `beta` are ignored
+    pub pipe: WaveGuide,
+    /// diameter of the open end, in m
+    pub diameter: f32,
+}
+
+impl Tip {
+    /// A tip `delay` seconds long.
+    pub fn new(delay: f32, diameter: f32, samples_per_second: u32) -> Result<Self, Error> {
+        check_positive("diameter", diameter)?;
+        Ok(Self {
+            pipe: WaveGuide::new(
+                (delay * samples_per_second as f32) as usize,
+                0.0,
+                0.0,
+                samples_per_second,
+            )?,
+            diameter,
+        })
+    }
+}
+
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tailpipe {
+    /// inner diameter of the pipe, in m
+    pub diameter: f32,
+    /// how much of the low end the open end reflects back into the tip
+    pub reflection: f32,
+    /// how much of the low end is radiated anyways
+    pub low_radiation: f32,
+    pub tip: Option<Tip>,
+    /// a resonator tuned to cancel drone, eg. a quarter wave or helmholtz resonator
+    pub resonator: Option<Biquad>,
+    /// how much the resonator cancels at its frequency, 0.0 - 1.0
+    pub resonator_depth: f32,
+    /// splits the radiated and reflected frequencies, set from the open end's diameter
+    pub radiation_lp: LowPassFilter,
+
+    // running values
+    /// the reflection arriving back at the muffler, fed into it with the next sample
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub returning: f32,
+}
+
+impl Tailpipe {
+    pub fn new(diameter: f32, samples_per_second: u32) -> Result<Self, Error> {
+        check_positive("diameter", diameter)?;
+        Ok(Self {
+            diameter,
+            reflection: 0.7,
+            low_radiation: 0.7,
+            tip: None,
+            resonator: None,
+            resonator_depth: 0.0,
+            radiation_lp: LowPassFilter::new(Self::corner(diameter), samples_per_second)?,
+            returning: 0.0,
+        })
+    }
+
+    /// the frequency above which an open end of `diameter` radiates more than it reflects
+    pub fn corner(diameter: f32) -> f32 {
+        SPEED_OF_SOUND / (PI2F * diameter / 2.0)
+    }
+
+    /// diameter of the open end
+    pub fn open_diameter(&self) -> f32 {
+        self.tip.as_ref().map_or(self.diameter, |tip| tip.diameter)
+    }
+
+    pub fn with_tip(mut self, tip: Tip, samples_per_second: u32) -> Result<Self, Error> {
+        self.tip = Some(tip);
+        self.set_diameter(self.diameter, samples_per_second)?;
+        Ok(self)
+    }
+
+    /// Sets the pipe's diameter. The radiation follows the tip's diameter, if there is one.
+    /// A diameter that isn't above 0 is refused, and everything is left as it was.
+    pub fn set_diameter(&mut self, diameter: f32, samples_per_second: u32) -> Result<(), Error> {
+        check_positive("diameter", diameter)?;
+        self.diameter = diameter;
+        self.radiation_lp
+            .retune(Self::corner(self.open_diameter()), samples_per_second)
+    }
+
+    pub fn resample(&mut self, samples_per_second: u32) -> Result<(), Error> {
+        self.radiation_lp.resample(samples_per_second);
+        if let Some(tip) = &mut self.tip {
+            tip.pipe.resample(samples_per_second)?;
+        }
+        if let Some(resonator) = &mut self.resonator {
+            resonator.resample(samples_per_second);
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.radiation_lp.last = 0.0;
+        self.returning = 0.0;
+        if let Some(tip) = &mut self.tip {
+            [&mut tip.pipe.chamber0, &mut tip.pipe.chamber1]
+                .iter_mut()
+                .for_each(|chamber| chamber.samples.data.iter_mut().for_each(|x| *x = 0.0));
+        }
+        if let Some(resonator) = &mut self.resonator {
+            resonator.clear();
+        }
+    }
+
+    /// takes in the muffler's output
+    /// returns the sound radiated from the open end, and sets `returning`
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let (returning, arriving) = match &mut self.tip {
+            Some(tip) => {
+                tip.pipe.alpha = 0.0;
+                tip.pipe.beta = 0.0;
+                tip.pipe.pop()
+            }
+            None => (0.0, sample),
+        };
+
+        let low = self.radiation_lp.filter(arriving);
+        let high = arriving - low;
+
+        let reflected = -low * self.reflection;
+        self.returning = match &mut self.tip {
+            Some(tip) => {
+                tip.pipe.push(sample, reflected);
+                returning
+            }
+            None => reflected,
+        };
+
+        let radiated = low.madd(self.low_radiation, high);
+        match &mut self.resonator {
+            Some(resonator) => resonator
+                .filter(radiated)
+                .madd(-self.resonator_depth, radiated),
+            None => radiated,
+        }
+    }
+}
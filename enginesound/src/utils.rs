@@ -10,6 +10,8 @@ pub trait FExt {
     fn cosf(self) -> Self;
     /// faster sin
     fn sinf(self) -> Self;
+    /// `clamp`, with NaN at `min` instead of NaN
+    fn clamp_nan(self, min: f32, max: f32) -> Self;
 }
 
 impl FExt for f32 {
@@ -35,4 +37,13 @@ impl FExt for f32 {
     fn sinf(self) -> f32 {
         (self - FRACT_PI_2).cosf()
     }
+
+    #[inline]
+    fn clamp_nan(self, min: f32, max: f32) -> f32 {
+        if self.is_nan() {
+            min
+        } else {
+            self.clamp(min, max)
+        }
+    }
 }
@@ -0,0 +1,588 @@
+//! ## Validate module ##
+//!
+//! Finds the parameters of an `Engine` that are out of range or make it unstable,
+//! and clamps them to safe values if asked to.
+//!
+//! A reflection of magnitude 1.0 is a closed end, which is fine as long as the other end of the
+//! waveguide lets some of the wave out. Only when both ends reflect everything does the wave never die down.
+//!
+#![warn(clippy::suboptimal_flops, clippy::use_self, clippy::dbg_macro)]
+use crate::filter::Filter;
+use crate::gen::{Crossover, Engine, LowPassFilter, Muffler, WaveGuide};
+use crate::network::{Network, Termination};
+use crate::tailpipe::Tailpipe;
+use std::fmt;
+
+/// the shortest ignition, in crank cycles; shorter ones are clicks, and 0.0 divides by zero
+pub const MIN_IGNITION_TIME: f32 = 0.01;
+/// the most a wave may keep after travelling through a waveguide and back
+pub const MAX_ROUND_TRIP: f32 = 0.99;
+/// the shortest delay line, in s; one sample at 10 kHz, shorter ones may round to no sample at all
+pub const MIN_DELAY: f32 = 0.0001;
+/// the longest delay line, in s
+pub const MAX_DELAY: f32 = 1.0;
+/// the lowest cutoff or center frequency of a filter, in Hz
+pub const MIN_CUTOFF: f32 = 1.0;
+/// the highest cutoff or center frequency of a filter, in Hz; below the nyquist frequency of 44.1 kHz
+pub const MAX_CUTOFF: f32 = 20000.0;
+
+/// A parameter out of its safe range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    /// where the parameter is in the engine, eg. `cylinders[2].ignition_time`
+    pub parameter: String,
+    pub value: f32,
+    /// what the value should be
+    pub expected: &'static str,
+    /// the closest safe value, which `validate` sets when clamping
+    pub safe: f32,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "`{}` is {}, expected {} (safe: {})",
+            self.parameter, self.value, self.expected, self.safe
+        )
+    }
+}
+
+/// Collects the issues, and clamps the values if `clamp` is set.
+struct Checker {
+    clamp: bool,
+    issues: Vec<Issue>,
+}
+
+impl Checker {
+    fn report(&mut self, parameter: String, value: &mut f32, expected: &'static str, safe: f32) {
+        self.issues.push(Issue {
+            parameter,
+            value: *value,
+            expected,
+            safe,
+        });
+        if self.clamp {
+            *value = safe;
+        }
+    }
+
+    fn finite(&mut self, parameter: String, value: &mut f32) {
+        if !value.is_finite() {
+            self.report(parameter, value, "a finite number", 0.0);
+        }
+    }
+
+    fn range(
+        &mut self,
+        parameter: String,
+        value: &mut f32,
+        min: f32,
+        max: f32,
+        expected: &'static str,
+    ) {
+        if !(min..=max).contains(value) {
+            let safe = if value.is_nan() {
+                0.0_f32.clamp(min, max)
+            } else {
+                value.clamp(min, max)
+            };
+            self.report(parameter, value, expected, safe);
+        }
+    }
+
+    /// a position in a cycle, wrapped around into 0.0 - 1.0
+    fn cycle(&mut self, parameter: String, value: &mut f32) {
+        if !(0.0..=1.0).contains(value) {
+            let safe = if value.is_finite() {
+                value.rem_euclid(1.0)
+            } else {
+                0.0
+            };
+            self.report(parameter, value, "0.0 - 1.0", safe);
+        }
+    }
+
+    fn reflection(&mut self, parameter: String, value: &mut f32) {
+        self.range(parameter, value, -1.0, 1.0, "-1.0 - 1.0");
+    }
+
+    /// `beta` against the strongest reflection its other end gets to
+    fn round_trip(&mut self, parameter: String, alpha: f32, beta: &mut f32) {
+        if (alpha * *beta).abs() >= 1.0 {
+            let safe = (MAX_ROUND_TRIP / alpha.abs()).copysign(*beta);
+            self.report(
+                parameter,
+                beta,
+                "a round trip with the other end below 1.0",
+                safe,
+            );
+        }
+    }
+
+    fn delay(&mut self, path: &str, guide: &mut WaveGuide) {
+        for (chamber, line) in [
+            ("chamber0", &mut guide.chamber0),
+            ("chamber1", &mut guide.chamber1),
+        ] {
+            self.range(
+                format!("{path}.{chamber}.samples.delay"),
+                &mut line.samples.delay,
+                MIN_DELAY,
+                MAX_DELAY,
+                "0.0001 - 1.0",
+            );
+        }
+    }
+
+    fn cutoff(&mut self, parameter: String, freq: &mut f32) {
+        self.range(parameter, freq, MIN_CUTOFF, MAX_CUTOFF, "1.0 - 20000.0");
+    }
+
+    /// the one pole's `delay` is 1 / its cutoff, 0.0 makes it NaN
+    fn low_pass(&mut self, path: &str, filter: &mut LowPassFilter) {
+        self.range(
+            format!("{path}.delay"),
+            &mut filter.delay,
+            1.0 / MAX_CUTOFF,
+            1.0 / MIN_CUTOFF,
+            "0.00005 - 1.0",
+        );
+    }
+
+    fn filter(&mut self, path: &str, filter: &mut Filter) {
+        let (parameter, freq) = match filter {
+            Filter::OnePole(filter) => return self.low_pass(&format!("{path}.OnePole"), filter),
+            Filter::Biquad(filter) => (format!("{path}.Biquad.freq"), &mut filter.freq),
+            Filter::Svf(filter) => (format!("{path}.Svf.freq"), &mut filter.freq),
+            Filter::DcBlocker(filter) => (format!("{path}.DcBlocker.freq"), &mut filter.freq),
+            Filter::Butterworth(filter) => (format!("{path}.Butterworth.freq"), &mut filter.freq),
+        };
+        self.cutoff(parameter, freq);
+    }
+
+    fn waveguide(&mut self, path: &str, guide: &mut WaveGuide) {
+        self.delay(path, guide);
+        self.reflection(format!("{path}.alpha"), &mut guide.alpha);
+        self.reflection(format!("{path}.beta"), &mut guide.beta);
+        self.round_trip(format!("{path}.beta"), guide.alpha, &mut guide.beta);
+    }
+
+    /// a waveguide whose `alpha` is set by a valve or port, between `open` and `closed`
+    fn ported(
+        &mut self,
+        path: &str,
+        guide: &mut WaveGuide,
+        (open_path, open): (&str, &mut f32),
+        (closed_path, closed): (&str, &mut f32),
+    ) {
+        self.delay(path, guide);
+        self.reflection(open_path.to_owned(), open);
+        self.reflection(closed_path.to_owned(), closed);
+        self.reflection(format!("{path}.beta"), &mut guide.beta);
+        self.round_trip(
+            format!("{path}.beta"),
+            open.abs().max(closed.abs()),
+            &mut guide.beta,
+        );
+    }
+
+    fn muffler(&mut self, path: &str, muffler: &mut Muffler) {
+        self.waveguide(&format!("{path}.straight_pipe"), &mut muffler.straight_pipe);
+        for (i, element) in muffler.muffler_elements.iter_mut().enumerate() {
+            self.waveguide(&format!("{path}.muffler_elements[{i}]"), element);
+        }
+    }
+
+    fn network(&mut self, path: &str, network: &mut Network) {
+        for pipe in network.pipes.iter_mut() {
+            self.delay(
+                &format!("{path}.pipes[{}].guide", pipe.name),
+                &mut pipe.guide,
+            );
+            self.range(
+                format!("{path}.pipes[{}].loss", pipe.name),
+                &mut pipe.loss,
+                0.0,
+                1.0,
+                "0.0 - 1.0",
+            );
+        }
+        for (i, terminal) in network.terminals.iter_mut().enumerate() {
+            match &mut terminal.termination {
+                Termination::Open { reflection } => {
+                    self.reflection(format!("{path}.terminals[{i}].reflection"), reflection)
+                }
+                Termination::Resonator { resonator, .. } => self.cutoff(
+                    format!("{path}.terminals[{i}].resonator.freq"),
+                    &mut resonator.freq,
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    fn tailpipe(&mut self, path: &str, tailpipe: &mut Tailpipe) {
+        self.reflection(format!("{path}.reflection"), &mut tailpipe.reflection);
+        self.range(
+            format!("{path}.resonator_depth"),
+            &mut tailpipe.resonator_depth,
+            0.0,
+            1.0,
+            "0.0 - 1.0",
+        );
+        self.low_pass(&format!("{path}.radiation_lp"), &mut tailpipe.radiation_lp);
+        if let Some(tip) = &mut tailpipe.tip {
+            self.delay(&format!("{path}.tip.pipe"), &mut tip.pipe);
+        }
+        if let Some(resonator) = &mut tailpipe.resonator {
+            self.cutoff(format!("{path}.resonator.freq"), &mut resonator.freq);
+        }
+    }
+
+    fn engine(&mut self, engine: &mut Engine) {
+        self.finite("rpm".to_owned(), &mut engine.rpm);
+        self.finite("intake_volume".to_owned(), &mut engine.intake_volume);
+        self.finite("exhaust_volume".to_owned(), &mut engine.exhaust_volume);
+        self.finite(
+            "engine_vibrations_volume".to_owned(),
+            &mut engine.engine_vibrations_volume,
+        );
+        self.finite(
+            "intake_noise_factor".to_owned(),
+            &mut engine.intake_noise_factor,
+        );
+        self.finite(
+            "crankshaft_fluctuation".to_owned(),
+            &mut engine.crankshaft_fluctuation,
+        );
+        self.range(
+            "intake_valve_shift".to_owned(),
+            &mut engine.intake_valve_shift,
+            -0.5,
+            0.5,
+            "-0.5 - 0.5",
+        );
+        self.range(
+            "exhaust_valve_shift".to_owned(),
+            &mut engine.exhaust_valve_shift,
+            -0.5,
+            0.5,
+            "-0.5 - 0.5",
+        );
+        self.filter("intake_noise_lp", &mut engine.intake_noise_lp);
+        self.filter(
+            "engine_vibration_filter",
+            &mut engine.engine_vibration_filter,
+        );
+        self.filter(
+            "crankshaft_fluctuation_lp",
+            &mut engine.crankshaft_fluctuation_lp,
+        );
+
+        for (i, cyl) in engine.cylinders.iter_mut().enumerate() {
+            let path = format!("cylinders[{i}]");
+            self.cycle(format!("{path}.crank_offset"), &mut cyl.crank_offset);
+            self.range(
+                format!("{path}.ignition_time"),
+                &mut cyl.ignition_time,
+                MIN_IGNITION_TIME,
+                1.0,
+                "0.01 - 1.0",
+            );
+            self.finite(
+                format!("{path}.piston_motion_factor"),
+                &mut cyl.piston_motion_factor,
+            );
+            self.finite(format!("{path}.ignition_factor"), &mut cyl.ignition_factor);
+            self.ported(
+                &format!("{path}.exhaust_waveguide"),
+                &mut cyl.exhaust_waveguide,
+                (
+                    &format!("{path}.exhaust_open_refl"),
+                    &mut cyl.exhaust_open_refl,
+                ),
+                (
+                    &format!("{path}.exhaust_closed_refl"),
+                    &mut cyl.exhaust_closed_refl,
+                ),
+            );
+            self.ported(
+                &format!("{path}.intake_waveguide"),
+                &mut cyl.intake_waveguide,
+                (
+                    &format!("{path}.intake_open_refl"),
+                    &mut cyl.intake_open_refl,
+                ),
+                (
+                    &format!("{path}.intake_closed_refl"),
+                    &mut cyl.intake_closed_refl,
+                ),
+            );
+            self.waveguide(
+                &format!("{path}.extractor_waveguide"),
+                &mut cyl.extractor_waveguide,
+            );
+        }
+
+        for (i, rotor) in engine.rotors.iter_mut().enumerate() {
+            let path = format!("rotors[{i}]");
+            self.cycle(format!("{path}.rotor_offset"), &mut rotor.rotor_offset);
+            self.range(
+                format!("{path}.ignition_time"),
+                &mut rotor.ignition_time,
+                MIN_IGNITION_TIME,
+                1.0,
+                "0.01 - 1.0",
+            );
+            self.finite(
+                format!("{path}.chamber_motion_factor"),
+                &mut rotor.chamber_motion_factor,
+            );
+            self.finite(
+                format!("{path}.ignition_factor"),
+                &mut rotor.ignition_factor,
+            );
+            self.range(
+                format!("{path}.housing_leak"),
+                &mut rotor.housing_leak,
+                0.0,
+                1.0,
+                "0.0 - 1.0",
+            );
+            self.ported(
+                &format!("{path}.exhaust_waveguide"),
+                &mut rotor.exhaust_waveguide,
+                (
+                    &format!("{path}.exhaust_open_refl"),
+                    &mut rotor.exhaust_open_refl,
+                ),
+                (
+                    &format!("{path}.exhaust_closed_refl"),
+                    &mut rotor.exhaust_closed_refl,
+                ),
+            );
+            self.ported(
+                &format!("{path}.intake_waveguide"),
+                &mut rotor.intake_waveguide,
+                (
+                    &format!("{path}.intake_open_refl"),
+                    &mut rotor.intake_open_refl,
+                ),
+                (
+                    &format!("{path}.intake_closed_refl"),
+                    &mut rotor.intake_closed_refl,
+                ),
+            );
+            self.waveguide(
+                &format!("{path}.extractor_waveguide"),
+                &mut rotor.extractor_waveguide,
+            );
+        }
+
+        if let Some(intake) = &mut engine.intake {
+            self.range(
+                "intake.throttle".to_owned(),
+                &mut intake.throttle,
+                0.0,
+                1.0,
+                "0.0 - 1.0",
+            );
+            for (i, runner) in intake.runners.iter_mut().enumerate() {
+                self.waveguide(&format!("intake.runners[{i}]"), runner);
+            }
+            self.waveguide("intake.snorkel", &mut intake.snorkel);
+            self.cutoff("intake.airbox.freq".to_owned(), &mut intake.airbox.freq);
+            self.low_pass("intake.throttle_noise_lp", &mut intake.throttle_noise_lp);
+            if let Some(turbo) = &mut intake.turbo {
+                self.low_pass("intake.turbo.hiss_lp", &mut turbo.hiss_lp);
+            }
+        }
+
+        if let Some(body) = &mut engine.body {
+            for (name, modes) in [
+                ("block", &mut body.block),
+                ("panels", &mut body.panels),
+                ("cabin", &mut body.cabin),
+            ] {
+                for (i, mode) in modes.iter_mut().enumerate() {
+                    self.cutoff(
+                        format!("body.{name}[{i}].resonator.freq"),
+                        &mut mode.resonator.freq,
+                    );
+                }
+            }
+            self.low_pass("body.mount_lp", &mut body.mount_lp);
+        }
+        if let Some(mechanical) = &mut engine.mechanical {
+            self.cutoff(
+                "mechanical.valvetrain.resonator.freq".to_owned(),
+                &mut mechanical.valvetrain.resonator.freq,
+            );
+            self.cutoff(
+                "mechanical.injectors.resonator.freq".to_owned(),
+                &mut mechanical.injectors.resonator.freq,
+            );
+        }
+
+        self.muffler("muffler", &mut engine.muffler);
+        if let Some(network) = &mut engine.exhaust {
+            self.network("exhaust", network);
+        }
+        if let Some(tailpipe) = &mut engine.tailpipe {
+            self.tailpipe("tailpipe", tailpipe);
+        }
+        for (i, bank) in engine.banks.iter_mut().enumerate() {
+            let path = format!("banks[{i}]");
+            self.range(
+                format!("{path}.pan"),
+                &mut bank.pan,
+                -1.0,
+                1.0,
+                "-1.0 - 1.0",
+            );
+            self.muffler(&format!("{path}.muffler"), &mut bank.muffler);
+            if let Some(network) = &mut bank.exhaust {
+                self.network(&format!("{path}.exhaust"), network);
+            }
+            if let Some(tailpipe) = &mut bank.tailpipe {
+                self.tailpipe(&format!("{path}.tailpipe"), tailpipe);
+            }
+        }
+        match &mut engine.crossover {
+            Some(Crossover::X { mix }) => {
+                self.range("crossover.mix".to_owned(), mix, 0.0, 1.0, "0.0 - 1.0")
+            }
+            Some(Crossover::H { pipe }) => self.waveguide("crossover.pipe", pipe),
+            None => {}
+        }
+    }
+}
+
+impl Engine {
+    /// Reports every parameter that is out of range or makes the engine unstable.
+    /// With `clamp`, they are also set to the closest safe values.
+    pub fn validate(&mut self, clamp: bool) -> Vec<Issue> {
+        let mut checker = Checker {
+            clamp,
+            issues: Vec::new(),
+        };
+        checker.engine(self);
+        checker.issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    fn checker(clamp: bool) -> Checker {
+        Checker {
+            clamp,
+            issues: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn round_trip_clamps_beta() {
+        let mut checker = checker(true);
+        let mut beta = -1.0;
+        checker.round_trip("beta".to_owned(), 1.0, &mut beta);
+        assert_eq!(beta, -MAX_ROUND_TRIP);
+        assert_eq!(checker.issues.len(), 1);
+        assert_eq!(checker.issues[0].value, -1.0);
+
+        let mut beta = 0.5;
+        checker.round_trip("beta".to_owned(), -2.0, &mut beta);
+        assert_eq!(beta, MAX_ROUND_TRIP / 2.0);
+    }
+
+    #[test]
+    fn round_trip_keeps_decaying_waves() {
+        let mut checker = checker(true);
+        let mut beta = -0.7;
+        checker.round_trip("beta".to_owned(), 1.0, &mut beta);
+        assert_eq!(beta, -0.7);
+        assert!(checker.issues.is_empty());
+    }
+
+    #[test]
+    fn cycle_wraps_around() {
+        let mut checker = checker(true);
+        for (value, safe) in [(1.25, 0.25), (-0.25, 0.75), (3.5, 0.5), (f32::NAN, 0.0)] {
+            let mut value = value;
+            checker.cycle("offset".to_owned(), &mut value);
+            assert_eq!(value, safe);
+        }
+        let mut value = 1.0;
+        checker.cycle("offset".to_owned(), &mut value);
+        assert_eq!(value, 1.0);
+        assert_eq!(checker.issues.len(), 4);
+    }
+
+    #[test]
+    fn range_clamps_nan_into_the_range() {
+        let mut checker = checker(true);
+        let mut value = f32::NAN;
+        checker.range("time".to_owned(), &mut value, MIN_IGNITION_TIME, 1.0, "");
+        assert_eq!(value, MIN_IGNITION_TIME);
+
+        let mut value = f32::NAN;
+        checker.range("pan".to_owned(), &mut value, -1.0, 1.0, "");
+        assert_eq!(value, 0.0);
+
+        let mut value = f32::INFINITY;
+        checker.range("pan".to_owned(), &mut value, -1.0, 1.0, "");
+        assert_eq!(value, 1.0);
+        assert_eq!(checker.issues.len(), 3);
+    }
+
+    #[test]
+    fn reports_without_clamping() {
+        let mut checker = checker(false);
+        let mut value = 2.0;
+        checker.reflection("alpha".to_owned(), &mut value);
+        assert_eq!(value, 2.0);
+        assert_eq!(checker.issues[0].safe, 1.0);
+    }
+
+    #[test]
+    fn empty_delays_are_clamped() {
+        let mut engine = Engine::new(48000).unwrap();
+        engine.muffler.straight_pipe.chamber0.samples.delay = 0.0;
+        engine.cylinders[0].exhaust_waveguide.chamber1.samples.delay = f32::NAN;
+        engine.intake_noise_lp = Filter::from(LowPassFilter::default());
+        assert_eq!(engine.validate(false).len(), 3);
+        assert_eq!(engine.resample(48000), Err(Error::EmptyDelay));
+
+        assert_eq!(engine.validate(true).len(), 3);
+        assert_eq!(
+            engine.muffler.straight_pipe.chamber0.samples.delay,
+            MIN_DELAY
+        );
+        assert_eq!(engine.resample(48000), Ok(()));
+    }
+
+    #[test]
+    fn default_engines_are_valid() {
+        for mut engine in [
+            Engine::new(48000).unwrap(),
+            Engine::diesel(48000).unwrap(),
+            Engine::rotary(48000).unwrap(),
+        ] {
+            assert_eq!(engine.validate(false), Vec::new());
+        }
+    }
+
+    #[test]
+    fn clamped_engine_is_valid() {
+        let mut engine = Engine::new(48000).unwrap();
+        engine.cylinders[0].ignition_time = 0.0;
+        engine.cylinders[1].crank_offset = -0.5;
+        engine.cylinders[2].exhaust_waveguide.beta = 1.5;
+        engine.rpm = f32::NAN;
+        assert_eq!(engine.validate(true).len(), 4);
+        assert_eq!(engine.validate(false), Vec::new());
+    }
+}